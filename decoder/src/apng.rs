@@ -0,0 +1,149 @@
+use wasm_bindgen::prelude::*;
+
+use crate::encoder::EncodeError;
+use crate::export::browser_delay;
+use crate::png::{check_size, compress_image, ihdr, write_chunk, ColorType, SIGNATURE};
+use crate::{DecodedGif, DisposalMethod, FrameDelta};
+
+#[wasm_bindgen]
+impl DecodedGif {
+    #[wasm_bindgen(js_name = toApng)]
+    pub fn to_apng_js(&self) -> Result<Box<[u8]>, JsError> {
+        Ok(self.to_apng()?.into())
+    }
+}
+
+impl DecodedGif {
+    /// Writes the animation as an animated PNG, with each frame covering the same rectangle and
+    /// disposed of the same way as in the GIF.
+    ///
+    /// Frames are written at the file's own resolution, even if they were decoded stretched to
+    /// square pixels. "Restore to background" clears to transparency, like browsers do, since
+    /// APNG has no background color. Delays below 2cs become 10cs, which is also how browsers
+    /// play them.
+    pub fn to_apng(&self) -> Result<Vec<u8>, EncodeError> {
+        // The first frame doubles as the default image, which every PNG needs
+        if self.num_frames == 0 {
+            return Err(EncodeError::NoFrames);
+        }
+
+        let (width, height) = self.native_size();
+        check_size(width, height)?;
+        let mut out = SIGNATURE.to_vec();
+        // The canvas can't be bigger than a GIF's, so these never truncate
+        write_chunk(
+            &mut out,
+            b"IHDR",
+            &ihdr(width as u32, height as u32, ColorType::Rgba),
+        );
+
+        // GIF loop counts are repeats after the first play, and APNG's are total plays
+        let plays = match self.max_loops {
+            None => 1,
+            Some(loops) => u32::from(loops).saturating_add(u32::from(loops != 0)),
+        };
+        let mut actl = Vec::with_capacity(8);
+        actl.extend((self.num_frames as u32).to_be_bytes());
+        actl.extend(plays.to_be_bytes());
+        write_chunk(&mut out, b"acTL", &actl);
+
+        // Shared between frame control and frame data chunks
+        let mut sequence = 0u32;
+        for i in 0..self.num_frames {
+            let frame = self.with_delta(i, |delta| ApngFrame::new(delta, i == 0, width, height))?;
+
+            let mut fctl = Vec::with_capacity(26);
+            fctl.extend(sequence.to_be_bytes());
+            for n in [frame.width, frame.height, frame.left, frame.top] {
+                fctl.extend((n as u32).to_be_bytes());
+            }
+            fctl.extend(frame.delay.to_be_bytes());
+            // Delays are in hundredths of a second
+            fctl.extend(100u16.to_be_bytes());
+            fctl.push(frame.dispose_op);
+            // Blend over the canvas, so transparent pixels show what's underneath
+            fctl.push(1);
+            write_chunk(&mut out, b"fcTL", &fctl);
+            sequence += 1;
+
+            let data = compress_image(frame.width, ColorType::Rgba, &frame.rgba);
+            if i == 0 {
+                write_chunk(&mut out, b"IDAT", &data);
+            } else {
+                let mut fdat = Vec::with_capacity(data.len() + 4);
+                fdat.extend(sequence.to_be_bytes());
+                fdat.extend(data);
+                write_chunk(&mut out, b"fdAT", &fdat);
+                sequence += 1;
+            }
+        }
+
+        write_chunk(&mut out, b"IEND", &[]);
+        Ok(out)
+    }
+}
+
+/// A frame cut down to the part that's inside the canvas.
+struct ApngFrame {
+    width: usize,
+    height: usize,
+    left: usize,
+    top: usize,
+    delay: u16,
+    dispose_op: u8,
+    rgba: Vec<u8>,
+}
+
+impl ApngFrame {
+    /// The first frame has to cover the whole canvas, so it's padded with transparency. Since
+    /// nothing was drawn before it, disposing of the padding as well changes nothing.
+    fn new(delta: &FrameDelta, first: bool, canvas_width: usize, canvas_height: usize) -> Self {
+        let (mut left, mut top) = (usize::from(delta.left), usize::from(delta.top));
+        let right = (left + usize::from(delta.width)).min(canvas_width);
+        let bottom = (top + usize::from(delta.height)).min(canvas_height);
+        left = left.min(right);
+        top = top.min(bottom);
+
+        let mut dispose_op = match delta.disposal_method {
+            DisposalMethod::Keep => 0,
+            DisposalMethod::RestoreBackground => 1,
+            DisposalMethod::RestorePrevious => 2,
+        };
+
+        let (x0, y0, x1, y1) = if first {
+            (0, 0, canvas_width, canvas_height)
+        } else if left == right || top == bottom {
+            // Entirely outside the canvas, but frames can't be empty. Disposing of it has no
+            // visible effect in the GIF, so it mustn't here either.
+            dispose_op = 0;
+            (0, 0, 1, 1)
+        } else {
+            (left, top, right, bottom)
+        };
+
+        let mut rgba = Vec::with_capacity((x1 - x0) * (y1 - y0) * 4);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let inside = (left..right).contains(&x) && (top..bottom).contains(&y);
+                let color = if inside {
+                    let i = (y - usize::from(delta.top)) * delta.image.width
+                        + (x - usize::from(delta.left));
+                    delta.image.data[i].into_arr()
+                } else {
+                    [0; 4]
+                };
+                rgba.extend(color);
+            }
+        }
+
+        Self {
+            width: x1 - x0,
+            height: y1 - y0,
+            left: x0,
+            top: y0,
+            delay: browser_delay(delta.delay),
+            dispose_op,
+            rgba,
+        }
+    }
+}
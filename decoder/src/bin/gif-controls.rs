@@ -0,0 +1,721 @@
+//! Command-line front end to the decoder, for looking inside GIFs and regenerating test fixtures.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Write as _};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::{env, fs};
+
+use gif_controls_decoder::{
+    decode, decode_lazy, export_range, export_retimed, read_structure, BlockKind, DecodedGif,
+    DisposalMethod, EncodeOptions, FrameOrder, GifBlock, GifStructure, RetimeOptions,
+};
+
+use crate::json::json_string;
+
+#[path = "../json.rs"]
+mod json;
+
+const USAGE: &str = "\
+Usage: gif-controls <command> [options] <file.gif>
+
+Commands:
+  inspect     Print the canvas size, loop count, and each frame's rectangle, delay, disposal
+              method and palette
+      --json        Print JSON instead of text
+      --palettes    List every palette color (always included in JSON)
+
+  extract     Write every composited frame to its own file, named after the input
+      --format F    png (default) or rgba, which is raw pixels with no header
+      --frame N     Only write frame N
+      --out DIR     Where to write the frames (default: the current directory)
+
+  convert     Write a new GIF with a range of frames, a different speed or order
+      --out FILE    Where to write the GIF (required)
+      --start N     First frame to keep (default: 0)
+      --end N       Frame to stop before (default: the last one)
+      --speed X     Playback speed, where 2 is twice as fast
+      --reverse     Play the frames backwards
+      --ping-pong   Play the frames forwards and then backwards
+      --loops N     Times to repeat, 0 for forever (default: the same as the input)
+      --interlace   Store frames interlaced
+
+  expected    Write the .bin frames and .json metadata that the decoder tests compare
+              against. Compress the .bin with `xz` to get the .bin.xz the tests read.
+      --out DIR     Where to write them (default: the current directory)
+
+  blocks      List every block in the file with its byte offset and length, as the decoder
+              reads it, along with anything the decoder would warn about
+      --json        Print JSON instead of text
+";
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("gif-controls: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(format!("no command given\n\n{USAGE}").into());
+    };
+
+    match command.as_str() {
+        "inspect" => inspect(&Args::parse(rest, &[], &["--json", "--palettes"])?),
+        "extract" => extract(&Args::parse(rest, &["--format", "--frame", "--out"], &[])?),
+        "convert" => convert(&Args::parse(
+            rest,
+            &["--out", "--start", "--end", "--speed", "--loops"],
+            &["--reverse", "--ping-pong", "--interlace"],
+        )?),
+        "expected" => expected(&Args::parse(rest, &["--out"], &[])?),
+        "blocks" => blocks(&Args::parse(rest, &[], &["--json"])?),
+        "help" | "--help" | "-h" => {
+            print!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(format!("unknown command {command:?}\n\n{USAGE}").into()),
+    }
+}
+
+/// A command's options and its one input file.
+struct Args {
+    values: HashMap<&'static str, String>,
+    switches: HashSet<&'static str>,
+    input: PathBuf,
+}
+
+impl Args {
+    /// `options` take a value from the argument after them, and `switches` stand alone.
+    fn parse(args: &[String], options: &[&'static str], switches: &[&'static str]) -> Result<Self> {
+        let mut values = HashMap::new();
+        let mut set = HashSet::new();
+        let mut input = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if let Some(&name) = options.iter().find(|&&name| name == arg) {
+                let value = args.next().ok_or_else(|| format!("{name} needs a value"))?;
+                values.insert(name, value.clone());
+            } else if let Some(&name) = switches.iter().find(|&&name| name == arg) {
+                set.insert(name);
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option {arg}").into());
+            } else if input.replace(PathBuf::from(arg)).is_some() {
+                return Err("only one input file can be given".into());
+            }
+        }
+
+        Ok(Self {
+            values,
+            switches: set,
+            input: input.ok_or("no input file given")?,
+        })
+    }
+
+    fn value<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.values
+            .get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| format!("invalid value {value:?} for {name}: {e}").into())
+            })
+            .transpose()
+    }
+
+    fn has(&self, switch: &str) -> bool {
+        self.switches.contains(switch)
+    }
+
+    fn read_input(&self) -> Result<Box<[u8]>> {
+        fs::read(&self.input)
+            .map(Vec::into_boxed_slice)
+            .map_err(|e| format!("couldn't read {}: {e}", self.input.display()).into())
+    }
+
+    /// The input's file name without its extension, to name output files after.
+    fn stem(&self) -> String {
+        self.input
+            .file_stem()
+            .map_or_else(|| "frame".into(), |s| s.to_string_lossy().into_owned())
+    }
+
+    fn out_dir(&self) -> Result<PathBuf> {
+        let dir = PathBuf::from(self.values.get("--out").map_or(".", String::as_str));
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+fn inspect(args: &Args) -> Result<()> {
+    let gif = decode(args.read_input()?)?;
+    if args.has("--json") {
+        print!("{}", inspect_json(&gif)?);
+    } else {
+        print!(
+            "{}",
+            inspect_text(&gif, &args.input, args.has("--palettes"))?
+        );
+    }
+    Ok(())
+}
+
+fn inspect_text(gif: &DecodedGif, path: &Path, palettes: bool) -> Result<String> {
+    let mut out = String::new();
+    let loops = match gif.max_loops {
+        None => "plays once".into(),
+        Some(0) => "loops forever".into(),
+        Some(n) => format!("repeats {n} times"),
+    };
+    writeln!(
+        out,
+        "{}: {}x{}, {} frames, {loops}",
+        path.display(),
+        gif.canvas_width,
+        gif.canvas_height,
+        gif.num_frames,
+    )?;
+    writeln!(out, "background: {}", gif.bg_color)?;
+    if let Some(ratio) = gif.pixel_aspect_ratio {
+        writeln!(out, "pixel aspect ratio: {ratio}")?;
+    }
+    match &gif.global_palette {
+        Some(palette) => write_palette(&mut out, "global palette", palette, palettes)?,
+        None => writeln!(out, "no global palette")?,
+    }
+
+    for (i, frame) in gif.frames().enumerate() {
+        let frame = frame?;
+        write!(
+            out,
+            "frame {i}: {}x{} at ({}, {}), {}cs, {}",
+            frame.width,
+            frame.height,
+            frame.left,
+            frame.top,
+            frame.delay,
+            disposal_name(frame.disposal_method),
+        )?;
+        if let Some(index) = frame.transparency_index {
+            write!(out, ", transparent {index}")?;
+        }
+        if frame.interlaced {
+            out.push_str(", interlaced");
+        }
+        if frame.user_input {
+            out.push_str(", waits for input");
+        }
+        out.push('\n');
+        if let Some(palette) = &frame.local_palette {
+            write_palette(&mut out, "  local palette", palette, palettes)?;
+        }
+    }
+
+    for comment in &gif.comments {
+        writeln!(
+            out,
+            "comment before frame {}: {:?}",
+            comment.frame, comment.text
+        )?;
+    }
+    for warning in &gif.warnings {
+        writeln!(out, "warning: {warning}")?;
+    }
+    Ok(out)
+}
+
+/// The number of colors in `palette`, and with `colors` set, the colors themselves, 8 per line.
+fn write_palette(out: &mut String, label: &str, palette: &[u8], colors: bool) -> Result<()> {
+    writeln!(out, "{label}: {} colors", palette.len() / 3)?;
+    if colors {
+        let indent = label.len() - label.trim_start().len() + 2;
+        for row in palette.chunks(3 * 8) {
+            let hex: Vec<String> = row.chunks_exact(3).map(hex_color).collect();
+            writeln!(out, "{:indent$}{}", "", hex.join(" "))?;
+        }
+    }
+    Ok(())
+}
+
+fn inspect_json(gif: &DecodedGif) -> Result<String> {
+    let mut out = String::from("{\n");
+    writeln!(out, "  \"width\": {},", gif.canvas_width)?;
+    writeln!(out, "  \"height\": {},", gif.canvas_height)?;
+    writeln!(out, "  \"loops\": {},", json_option(gif.max_loops))?;
+    writeln!(out, "  \"background\": {},", json_string(&gif.bg_color))?;
+    writeln!(
+        out,
+        "  \"pixelAspectRatio\": {},",
+        json_option(gif.pixel_aspect_ratio)
+    )?;
+    writeln!(
+        out,
+        "  \"globalPalette\": {},",
+        json_palette(gif.global_palette.as_deref())
+    )?;
+
+    out.push_str("  \"frames\": [\n");
+    for (i, frame) in gif.frames().enumerate() {
+        let frame = frame?;
+        let separator = if i + 1 < gif.num_frames { "," } else { "" };
+        writeln!(
+            out,
+            "    {{\"width\": {}, \"height\": {}, \"top\": {}, \"left\": {}, \"delay\": {}, \
+             \"disposal\": \"{}\", \"transparent\": {}, \"interlaced\": {}, \
+             \"userInput\": {}, \"localPalette\": {}}}{separator}",
+            frame.width,
+            frame.height,
+            frame.top,
+            frame.left,
+            frame.delay,
+            disposal_name(frame.disposal_method),
+            json_option(frame.transparency_index),
+            frame.interlaced,
+            frame.user_input,
+            json_palette(frame.local_palette.as_deref()),
+        )?;
+    }
+    out.push_str("  ],\n");
+
+    let comments: Vec<String> = gif
+        .comments
+        .iter()
+        .map(|c| {
+            format!(
+                "{{\"frame\": {}, \"text\": {}}}",
+                c.frame,
+                json_string(&c.text)
+            )
+        })
+        .collect();
+    writeln!(out, "  \"comments\": [{}],", comments.join(", "))?;
+    let warnings: Vec<String> = gif
+        .warnings
+        .iter()
+        .map(|w| json_string(&w.to_string()))
+        .collect();
+    writeln!(out, "  \"warnings\": [{}]", warnings.join(", "))?;
+    out.push_str("}\n");
+    Ok(out)
+}
+
+fn extract(args: &Args) -> Result<()> {
+    let raw = match args.values.get("--format").map(String::as_str) {
+        None | Some("png") => false,
+        Some("rgba") => true,
+        Some(format) => return Err(format!("unknown format {format:?}").into()),
+    };
+    let only = args.value::<usize>("--frame")?;
+
+    // Lazy, so a single frame doesn't composite the whole file
+    let gif = decode_lazy(args.read_input()?)?;
+    let dir = args.out_dir()?;
+    let stem = args.stem();
+    let digits = gif.num_frames.saturating_sub(1).to_string().len();
+    let frames = match only {
+        Some(i) if i >= gif.num_frames => {
+            return Err(format!("there's no frame {i}, only {}", gif.num_frames).into())
+        }
+        Some(i) => i..i + 1,
+        None => 0..gif.num_frames,
+    };
+
+    for i in frames.clone() {
+        let (data, extension) = if raw {
+            (gif.get(i)?.image_data.into_vec(), "rgba")
+        } else {
+            (gif.frame_png(i)?, "png")
+        };
+        fs::write(dir.join(format!("{stem}-{i:0digits$}.{extension}")), data)?;
+    }
+
+    println!(
+        "wrote {} {}x{} frames to {}",
+        frames.len(),
+        gif.canvas_width,
+        gif.canvas_height,
+        dir.display(),
+    );
+    Ok(())
+}
+
+fn convert(args: &Args) -> Result<()> {
+    let out = args.values.get("--out").ok_or("--out is required")?;
+    let mut data = args.read_input()?;
+
+    let order = match (args.has("--reverse"), args.has("--ping-pong")) {
+        (true, true) => return Err("--reverse and --ping-pong can't be used together".into()),
+        (true, false) => Some(FrameOrder::Reverse),
+        (false, true) => Some(FrameOrder::PingPong),
+        (false, false) => None,
+    };
+    let speed = args.value::<f64>("--speed")?;
+    let start = args.value::<usize>("--start")?;
+    let end = args.value::<usize>("--end")?;
+
+    let loops = match args.value::<u16>("--loops")? {
+        Some(loops) => Some(loops),
+        None => decode_lazy(data.clone())?.max_loops,
+    };
+    let options = EncodeOptions {
+        loops,
+        interlace: args.has("--interlace"),
+    };
+
+    let retime = speed.is_some() || order.is_some();
+    // With nothing else to do, the whole file is copied through the encoder
+    if start.is_some() || end.is_some() || !retime {
+        let range = export_range(
+            data,
+            start.unwrap_or(0),
+            end.unwrap_or(usize::MAX),
+            &options,
+        )?;
+        data = range.into();
+    }
+    if retime {
+        let retime = RetimeOptions {
+            speed: speed.unwrap_or(1.0),
+            order: order.unwrap_or_default(),
+            ..Default::default()
+        };
+        data = export_retimed(data, &retime, &options)?.into();
+    }
+
+    fs::write(out, data)?;
+    Ok(())
+}
+
+fn expected(args: &Args) -> Result<()> {
+    let gif = decode(args.read_input()?)?;
+    let dir = args.out_dir()?;
+    let stem = args.stem();
+
+    // Width and height, then every frame's RGBA pixels
+    let frame_size = usize::from(gif.canvas_width) * usize::from(gif.canvas_height) * 4;
+    let mut bin = Vec::with_capacity(4 + frame_size * gif.num_frames);
+    bin.extend(gif.canvas_width.to_le_bytes());
+    bin.extend(gif.canvas_height.to_le_bytes());
+
+    let mut json = String::from("{\n");
+    writeln!(json, "  \"width\": {},", gif.canvas_width)?;
+    writeln!(json, "  \"height\": {},", gif.canvas_height)?;
+    writeln!(json, "  \"loops\": {},", json_option(gif.max_loops))?;
+    json.push_str("  \"frames\": [");
+    for (i, frame) in gif.frames().enumerate() {
+        let frame = frame?;
+        bin.extend_from_slice(&frame.image_data);
+
+        let separator = if i == 0 { "" } else { "," };
+        write!(
+            json,
+            "{separator}\n    {{\n      \"width\": {},\n      \"height\": {},\n      \
+             \"top\": {},\n      \"left\": {},\n      \"delay\": {}\n    }}",
+            frame.width, frame.height, frame.top, frame.left, frame.delay,
+        )?;
+    }
+    json.push_str("\n  ]\n}\n");
+
+    let bin_path = dir.join(format!("{stem}.bin"));
+    let json_path = dir.join(format!("{stem}.json"));
+    fs::write(&bin_path, bin)?;
+    fs::write(&json_path, json)?;
+    println!("wrote {} and {}", bin_path.display(), json_path.display());
+    Ok(())
+}
+
+fn blocks(args: &Args) -> Result<()> {
+    let data = args.read_input()?;
+    let structure = read_structure(&data);
+    if args.has("--json") {
+        print!("{}", blocks_json(&structure)?);
+    } else {
+        print!("{}", blocks_text(&structure, data.len() as u64)?);
+    }
+    Ok(())
+}
+
+fn blocks_text(structure: &GifStructure, file_len: u64) -> Result<String> {
+    fn write_block(out: &mut String, block: &GifBlock, depth: usize, width: usize) -> Result<()> {
+        let (name, details) = describe_block(&block.kind);
+        write!(
+            out,
+            "{:>width$} {:>width$}  {:indent$}{name}",
+            block.offset,
+            block.len,
+            "",
+            indent = depth * 2,
+        )?;
+        if !details.is_empty() {
+            write!(out, ": {details}")?;
+        }
+        match block.kind {
+            // Far too many to list one by one
+            BlockKind::ImageData { .. } => {
+                let total: usize = block.sub_blocks.iter().map(|&len| usize::from(len)).sum();
+                write!(
+                    out,
+                    ", {total} bytes in {} sub-blocks",
+                    block.sub_blocks.len()
+                )?;
+            }
+            _ if !block.sub_blocks.is_empty() => {
+                let sizes: Vec<String> = block.sub_blocks.iter().map(u8::to_string).collect();
+                write!(out, " [{}]", sizes.join(", "))?;
+            }
+            _ => {}
+        }
+        out.push('\n');
+
+        for child in &block.children {
+            write_block(out, child, depth + 1, width)?;
+        }
+        Ok(())
+    }
+
+    let width = file_len.to_string().len().max("offset".len());
+    let mut out = format!("{:>width$} {:>width$}  block\n", "offset", "length");
+    for block in &structure.blocks {
+        write_block(&mut out, block, 0, width)?;
+    }
+
+    if let Some(error) = &structure.error {
+        writeln!(out, "stopped at byte {}: {error}", structure.end)?;
+    } else if structure.end < file_len {
+        writeln!(
+            out,
+            "{} bytes after the trailer are ignored",
+            file_len - structure.end
+        )?;
+    }
+    for warning in &structure.warnings {
+        writeln!(out, "warning: {warning}")?;
+    }
+    Ok(out)
+}
+
+/// A block's name and a summary of its fields.
+fn describe_block(kind: &BlockKind) -> (&'static str, String) {
+    let (name, details) = match kind {
+        BlockKind::Header { version } => ("header", format!("GIF{version}")),
+        BlockKind::LogicalScreenDescriptor {
+            width,
+            height,
+            background_index,
+            aspect_ratio,
+        } => (
+            "logical screen descriptor",
+            format!("{width}x{height}, background {background_index}, aspect ratio {aspect_ratio}"),
+        ),
+        BlockKind::ColorTable { colors } => ("color table", format!("{colors} colors")),
+        BlockKind::GraphicsControl {
+            disposal_method,
+            user_input,
+            delay,
+            transparent,
+        } => {
+            let mut details = format!("{delay}cs, {}", disposal_name(*disposal_method));
+            if let Some(index) = transparent {
+                details += &format!(", transparent {index}");
+            }
+            if *user_input {
+                details += ", waits for input";
+            }
+            ("graphics control extension", details)
+        }
+        BlockKind::Comment => ("comment extension", String::new()),
+        BlockKind::PlainText => ("plain text extension", String::new()),
+        BlockKind::Application { identifier } => {
+            ("application extension", format!("{identifier:?}"))
+        }
+        BlockKind::Extension { label } => ("extension", format!("label 0x{label:02x}")),
+        BlockKind::Image {
+            left,
+            top,
+            width,
+            height,
+            interlaced,
+        } => {
+            let mut details = format!("{width}x{height} at ({left}, {top})");
+            if *interlaced {
+                details += ", interlaced";
+            }
+            ("image descriptor", details)
+        }
+        BlockKind::ImageData { min_code_size } => {
+            ("image data", format!("minimum code size {min_code_size}"))
+        }
+        BlockKind::Trailer => ("trailer", String::new()),
+        BlockKind::Unknown => ("unknown bytes", String::new()),
+    };
+    (name, details)
+}
+
+fn blocks_json(structure: &GifStructure) -> Result<String> {
+    fn write_block(out: &mut String, block: &GifBlock, indent: usize) -> Result<()> {
+        let pad = " ".repeat(indent);
+        let (kind, fields) = block_json_fields(&block.kind);
+        write!(
+            out,
+            "{pad}{{\"offset\": {}, \"length\": {}, \"type\": \"{kind}\"",
+            block.offset, block.len,
+        )?;
+        for (key, value) in fields {
+            write!(out, ", \"{key}\": {value}")?;
+        }
+        if !block.sub_blocks.is_empty() {
+            let sizes: Vec<String> = block.sub_blocks.iter().map(u8::to_string).collect();
+            write!(out, ", \"subBlocks\": [{}]", sizes.join(", "))?;
+        }
+        if !block.children.is_empty() {
+            out.push_str(", \"children\": [\n");
+            for (i, child) in block.children.iter().enumerate() {
+                write_block(out, child, indent + 2)?;
+                out.push_str(if i + 1 < block.children.len() {
+                    ",\n"
+                } else {
+                    "\n"
+                });
+            }
+            out.push_str(&pad);
+            out.push(']');
+        }
+        out.push('}');
+        Ok(())
+    }
+
+    let mut out = String::from("{\n  \"blocks\": [\n");
+    for (i, block) in structure.blocks.iter().enumerate() {
+        write_block(&mut out, block, 4)?;
+        out.push_str(if i + 1 < structure.blocks.len() {
+            ",\n"
+        } else {
+            "\n"
+        });
+    }
+    out.push_str("  ],\n");
+    writeln!(out, "  \"end\": {},", structure.end)?;
+    let error = structure
+        .error
+        .as_ref()
+        .map(|e| json_string(&e.to_string()));
+    writeln!(out, "  \"error\": {},", json_option(error))?;
+    let warnings: Vec<String> = structure
+        .warnings
+        .iter()
+        .map(|w| json_string(&w.to_string()))
+        .collect();
+    writeln!(out, "  \"warnings\": [{}]", warnings.join(", "))?;
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// A block's type and fields, with the values already written as JSON.
+fn block_json_fields(kind: &BlockKind) -> (&'static str, Vec<(&'static str, String)>) {
+    match kind {
+        BlockKind::Header { version } => ("header", vec![("version", json_string(version))]),
+        BlockKind::LogicalScreenDescriptor {
+            width,
+            height,
+            background_index,
+            aspect_ratio,
+        } => (
+            "logicalScreenDescriptor",
+            vec![
+                ("width", width.to_string()),
+                ("height", height.to_string()),
+                ("backgroundIndex", background_index.to_string()),
+                ("aspectRatio", aspect_ratio.to_string()),
+            ],
+        ),
+        BlockKind::ColorTable { colors } => ("colorTable", vec![("colors", colors.to_string())]),
+        BlockKind::GraphicsControl {
+            disposal_method,
+            user_input,
+            delay,
+            transparent,
+        } => (
+            "graphicsControl",
+            vec![
+                (
+                    "disposal",
+                    format!("\"{}\"", disposal_name(*disposal_method)),
+                ),
+                ("userInput", user_input.to_string()),
+                ("delay", delay.to_string()),
+                ("transparent", json_option(*transparent)),
+            ],
+        ),
+        BlockKind::Comment => ("comment", vec![]),
+        BlockKind::PlainText => ("plainText", vec![]),
+        BlockKind::Application { identifier } => {
+            ("application", vec![("identifier", json_string(identifier))])
+        }
+        BlockKind::Extension { label } => ("extension", vec![("label", label.to_string())]),
+        BlockKind::Image {
+            left,
+            top,
+            width,
+            height,
+            interlaced,
+        } => (
+            "image",
+            vec![
+                ("left", left.to_string()),
+                ("top", top.to_string()),
+                ("width", width.to_string()),
+                ("height", height.to_string()),
+                ("interlaced", interlaced.to_string()),
+            ],
+        ),
+        BlockKind::ImageData { min_code_size } => (
+            "imageData",
+            vec![("minCodeSize", min_code_size.to_string())],
+        ),
+        BlockKind::Trailer => ("trailer", vec![]),
+        BlockKind::Unknown => ("unknown", vec![]),
+    }
+}
+
+fn disposal_name(method: DisposalMethod) -> &'static str {
+    match method {
+        DisposalMethod::Keep => "keep",
+        DisposalMethod::RestoreBackground => "background",
+        DisposalMethod::RestorePrevious => "previous",
+    }
+}
+
+fn hex_color(rgb: &[u8]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+fn json_option<T: Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".into(), |v| v.to_string())
+}
+
+fn json_palette(palette: Option<&[u8]>) -> String {
+    match palette {
+        Some(palette) => {
+            let colors: Vec<String> = palette
+                .chunks_exact(3)
+                .map(|rgb| format!("\"{}\"", hex_color(rgb)))
+                .collect();
+            format!("[{}]", colors.join(", "))
+        }
+        None => "null".into(),
+    }
+}
@@ -0,0 +1,248 @@
+//! Just enough of zlib to write PNGs: deflate with fixed Huffman codes, plus the checksums.
+
+use crate::util::BitWriter;
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// How many earlier positions with the same hash are tried before settling for the best so far
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Compresses `data` into a zlib stream.
+pub(crate) fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window and default compression level
+    let mut out = vec![0x78, 0x9c];
+
+    let compressed = deflate_fixed(data);
+    // Stored blocks cost 5 bytes per 64K, so fall back to them for incompressible data
+    if compressed.len() < data.len() + data.len() / 0xffff * 5 + 5 {
+        out.extend(compressed);
+    } else {
+        out.extend(deflate_stored(data));
+    }
+
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 0xffff * 5 + 5);
+    let mut chunks = data.chunks(0xffff).peekable();
+    if chunks.peek().is_none() {
+        // Final, stored, and empty
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+
+    while let Some(chunk) = chunks.next() {
+        out.push(u8::from(chunks.peek().is_none()));
+        // Chunks are at most 0xffff long
+        let len = chunk.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+
+    out
+}
+
+/// A single final block using the fixed Huffman codes, with matches found through hash chains.
+fn deflate_fixed(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    // Final block, fixed codes
+    bits.put(1, 1);
+    bits.put(1, 2);
+
+    let mut matcher = Matcher::new();
+    let mut i = 0;
+    while i < data.len() {
+        match matcher.longest_match(data, i) {
+            Some((len, dist)) => {
+                put_length(&mut bits, len);
+                put_distance(&mut bits, dist);
+                for j in i..i + len {
+                    matcher.insert(data, j);
+                }
+                i += len;
+            }
+            None => {
+                put_symbol(&mut bits, data[i].into());
+                matcher.insert(data, i);
+                i += 1;
+            }
+        }
+    }
+
+    // End of block
+    put_symbol(&mut bits, 256);
+    bits.finish()
+}
+
+/// Chains together earlier positions that start with the same three bytes.
+struct Matcher {
+    /// Most recent position for each hash
+    head: Vec<usize>,
+    /// The position before each one in the window with the same hash
+    prev: Vec<usize>,
+}
+
+impl Matcher {
+    fn new() -> Self {
+        Self {
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            prev: vec![usize::MAX; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(data: &[u8], i: usize) -> usize {
+        let v = u32::from(data[i]) << 16 | u32::from(data[i + 1]) << 8 | u32::from(data[i + 2]);
+        (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, data: &[u8], i: usize) {
+        if i + MIN_MATCH <= data.len() {
+            let h = Self::hash(data, i);
+            self.prev[i % WINDOW_SIZE] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+
+    /// The longest earlier match for the bytes at `i`, as a length and distance.
+    fn longest_match(&self, data: &[u8], i: usize) -> Option<(usize, usize)> {
+        if i + MIN_MATCH > data.len() {
+            return None;
+        }
+
+        let max_len = MAX_MATCH.min(data.len() - i);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[Self::hash(data, i)];
+        for _ in 0..MAX_CHAIN {
+            // Positions only go back, and older ones have been overwritten in `prev`
+            if candidate == usize::MAX || i - candidate > WINDOW_SIZE - 1 {
+                break;
+            }
+
+            let len = data[candidate..]
+                .iter()
+                .zip(&data[i..i + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= MIN_MATCH && best.is_none_or(|(best_len, _)| len > best_len) {
+                best = Some((len, i - candidate));
+                if len == max_len {
+                    break;
+                }
+            }
+
+            let next = self.prev[candidate % WINDOW_SIZE];
+            if next == usize::MAX || next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+
+        best
+    }
+}
+
+/// Writes a literal byte, the end of block marker, or a length code.
+fn put_symbol(bits: &mut BitWriter, symbol: u16) {
+    let (code, len) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    put_huffman(bits, code, len);
+}
+
+fn put_length(bits: &mut BitWriter, len: usize) {
+    // LENGTH_BASES starts at MIN_MATCH, so this always finds one
+    let i = LENGTH_BASES.partition_point(|&base| usize::from(base) <= len) - 1;
+    // 258 could also be written as 227 plus 31, but has its own code
+    put_symbol(bits, 257 + i as u16);
+    put_extra(
+        bits,
+        len - usize::from(LENGTH_BASES[i]),
+        LENGTH_EXTRA_BITS[i],
+    );
+}
+
+fn put_distance(bits: &mut BitWriter, dist: usize) {
+    let i = DISTANCE_BASES.partition_point(|&base| usize::from(base) <= dist) - 1;
+    put_huffman(bits, i as u16, 5);
+    put_extra(
+        bits,
+        dist - usize::from(DISTANCE_BASES[i]),
+        DISTANCE_EXTRA_BITS[i],
+    );
+}
+
+/// Huffman codes are packed starting from their most significant bit, unlike everything else.
+fn put_huffman(bits: &mut BitWriter, code: u16, len: u8) {
+    let reversed = code.reverse_bits() >> (16 - len);
+    bits.put(reversed.into(), len);
+}
+
+fn put_extra(bits: &mut BitWriter, value: usize, len: u8) {
+    if len > 0 {
+        bits.put(value, len);
+    }
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1, 0);
+    // Sums can't overflow in this many bytes before being reduced
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[usize::from((crc as u8) ^ byte)] ^ (crc >> 8)
+    })
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
@@ -0,0 +1,334 @@
+use std::io::{self, Write};
+
+use byteorder::{WriteBytesExt, LE};
+use thiserror::Error;
+use wasm_bindgen::prelude::*;
+
+use crate::quantize::quantize;
+use crate::util::{lzw_compress, LZWError};
+use crate::{interlace, DecodeError, DisposalMethod, IndexedFrame};
+
+#[derive(Error, Debug)]
+pub enum EncodeError {
+    #[error("IO error: {0}")]
+    IO(#[from] io::Error),
+
+    #[error("Expected {expected} bytes of pixel data, got {actual}")]
+    WrongDataLength { expected: usize, actual: usize },
+    #[error("Palette has {0} colors, but a GIF palette holds 1 to 256")]
+    InvalidPaletteSize(usize),
+    #[error("Frame extends past the edge of the canvas")]
+    FrameOutsideCanvas,
+    #[error("Color index {index} is past the end of a palette with {colors} colors")]
+    IndexOutOfBounds { index: u8, colors: usize },
+
+    #[error("LZW compression error: {0}")]
+    LZWError(#[from] LZWError),
+
+    #[error("Couldn't decode the source GIF: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("Frame range {start}..{end} has no frames in it")]
+    InvalidRange { start: usize, end: usize },
+    #[error("Speed {0} isn't a positive number")]
+    InvalidSpeed(f64),
+    #[error("There are no frames to export")]
+    NoFrames,
+    #[error("Output would be {width}x{height} pixels, which is too large")]
+    TooLarge { width: usize, height: usize },
+    #[error("The canvas has no pixels, which PNG doesn't allow")]
+    EmptyImage,
+}
+
+/// Settings for the whole file being encoded.
+#[wasm_bindgen]
+#[derive(Default, Clone, Copy, Debug)]
+pub struct EncodeOptions {
+    /// How many times to repeat the animation, with 0 meaning forever. `None` leaves out the
+    /// NETSCAPE2.0 extension, so most viewers play it once.
+    pub loops: Option<u16>,
+
+    /// Store rows interlaced, so a partly downloaded frame can be shown at low detail.
+    pub interlace: bool,
+}
+
+#[wasm_bindgen]
+impl EncodeOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The pixels of a frame to encode.
+#[derive(Clone, Debug)]
+pub enum FramePixels {
+    /// 4 bytes per pixel. Colors are quantized if there are more than fit in a palette, and
+    /// pixels less than half opaque become transparent.
+    Rgba(Box<[u8]>),
+    /// One palette index per pixel, written out as is
+    Indexed {
+        indices: Box<[u8]>,
+        /// RGB order, 1 to 256 colors
+        palette: Box<[u8]>,
+        transparent: Option<u8>,
+    },
+}
+
+/// One frame of an animation to encode, covering a rectangle of the canvas.
+#[derive(Clone, Debug)]
+pub struct EncodeFrame {
+    pub width: u16,
+    pub height: u16,
+    pub top: u16,
+    pub left: u16,
+    /// In hundredths of a second
+    pub delay: u16,
+    pub disposal_method: DisposalMethod,
+    pub user_input: bool,
+    pub pixels: FramePixels,
+}
+
+impl EncodeFrame {
+    /// A frame covering the canvas from the top left, from RGBA pixels.
+    pub fn rgba(width: u16, height: u16, data: Box<[u8]>, delay: u16) -> Self {
+        Self {
+            width,
+            height,
+            top: 0,
+            left: 0,
+            delay,
+            disposal_method: DisposalMethod::Keep,
+            user_input: false,
+            pixels: FramePixels::Rgba(data),
+        }
+    }
+}
+
+impl From<IndexedFrame> for EncodeFrame {
+    fn from(frame: IndexedFrame) -> Self {
+        Self {
+            width: frame.width,
+            height: frame.height,
+            top: frame.top,
+            left: frame.left,
+            delay: frame.delay,
+            disposal_method: frame.disposal_method,
+            user_input: frame.user_input,
+            pixels: FramePixels::Indexed {
+                indices: frame.indices,
+                palette: frame.palette,
+                transparent: frame.transparent,
+            },
+        }
+    }
+}
+
+/// Writes a GIF89a file one frame at a time. Every frame gets its own color table, so there's
+/// no global one.
+pub struct GifEncoder<W: Write> {
+    wtr: W,
+    canvas_width: u16,
+    canvas_height: u16,
+    options: EncodeOptions,
+}
+
+impl<W: Write> GifEncoder<W> {
+    /// Writes the header, so frames can follow right away.
+    pub fn new(
+        wtr: W,
+        canvas_width: u16,
+        canvas_height: u16,
+        options: &EncodeOptions,
+    ) -> Result<Self, EncodeError> {
+        let mut encoder = Self {
+            wtr,
+            canvas_width,
+            canvas_height,
+            options: *options,
+        };
+        encoder.write_header()?;
+        Ok(encoder)
+    }
+
+    fn write_header(&mut self) -> Result<(), EncodeError> {
+        let w = &mut self.wtr;
+        w.write_all(b"GIF89a")?;
+        w.write_u16::<LE>(self.canvas_width)?;
+        w.write_u16::<LE>(self.canvas_height)?;
+        // No global color table, 8 bits per primary, background index 0, square pixels
+        w.write_all(&[0x70, 0, 0])?;
+
+        if let Some(loops) = self.options.loops {
+            w.write_all(&[0x21, 0xff, 11])?;
+            w.write_all(b"NETSCAPE2.0")?;
+            w.write_all(&[3, 1])?;
+            w.write_u16::<LE>(loops)?;
+            w.write_all(&[0])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_frame(&mut self, frame: &EncodeFrame) -> Result<(), EncodeError> {
+        let right = u32::from(frame.left) + u32::from(frame.width);
+        let bottom = u32::from(frame.top) + u32::from(frame.height);
+        if right > self.canvas_width.into() || bottom > self.canvas_height.into() {
+            return Err(EncodeError::FrameOutsideCanvas);
+        }
+
+        let num_pixels = usize::from(frame.width) * usize::from(frame.height);
+        let (mut indices, palette, transparent) = match &frame.pixels {
+            FramePixels::Rgba(data) => {
+                check_length(data.len(), num_pixels * 4)?;
+                let quantized = quantize(data);
+                let palette = quantized.palette.concat();
+                (quantized.indices, palette, quantized.transparent)
+            }
+            FramePixels::Indexed {
+                indices,
+                palette,
+                transparent,
+            } => {
+                check_length(indices.len(), num_pixels)?;
+                (indices.to_vec(), palette.to_vec(), *transparent)
+            }
+        };
+
+        let num_colors = palette.len() / 3;
+        if palette.len() % 3 != 0 || !(1..=256).contains(&num_colors) {
+            return Err(EncodeError::InvalidPaletteSize(num_colors));
+        }
+        // The padding at the end of the color table would be shown instead
+        if let Some(&index) = indices
+            .iter()
+            .chain(&transparent)
+            .find(|&&i| usize::from(i) >= num_colors)
+        {
+            return Err(EncodeError::IndexOutOfBounds {
+                index,
+                colors: num_colors,
+            });
+        }
+        // Palettes are stored with a power of two size, at least 2
+        let size_bits = num_colors.next_power_of_two().trailing_zeros().max(1);
+
+        let w = &mut self.wtr;
+
+        // Graphic control extension
+        let disposal: u8 = match frame.disposal_method {
+            DisposalMethod::Keep => 1,
+            DisposalMethod::RestoreBackground => 2,
+            DisposalMethod::RestorePrevious => 3,
+        };
+        let flags =
+            disposal << 2 | u8::from(frame.user_input) << 1 | u8::from(transparent.is_some());
+        w.write_all(&[0x21, 0xf9, 4, flags])?;
+        w.write_u16::<LE>(frame.delay)?;
+        w.write_all(&[transparent.unwrap_or(0), 0])?;
+
+        // Image descriptor, with a local color table
+        w.write_all(&[0x2c])?;
+        w.write_u16::<LE>(frame.left)?;
+        w.write_u16::<LE>(frame.top)?;
+        w.write_u16::<LE>(frame.width)?;
+        w.write_u16::<LE>(frame.height)?;
+        let interlace_flag = if self.options.interlace { 0x40 } else { 0 };
+        // size_bits is at most 8, so this never truncates
+        w.write_all(&[0x80 | interlace_flag | (size_bits - 1) as u8])?;
+
+        w.write_all(&palette)?;
+        w.write_all(&vec![0; (3 << size_bits) - palette.len()])?;
+
+        if self.options.interlace {
+            indices = interlace(&indices, frame.width.into(), frame.height.into());
+        }
+
+        let min_code_size = size_bits.max(2) as u8;
+        let data = lzw_compress(&indices, min_code_size)?;
+        w.write_all(&[min_code_size])?;
+        for block in data.chunks(255) {
+            // Chunks are at most 255 long
+            w.write_all(&[block.len() as u8])?;
+            w.write_all(block)?;
+        }
+        w.write_all(&[0])?;
+
+        Ok(())
+    }
+
+    /// Writes the trailer and hands back the writer.
+    pub fn finish(mut self) -> Result<W, EncodeError> {
+        self.wtr.write_all(&[0x3b])?;
+        self.wtr.flush()?;
+        Ok(self.wtr)
+    }
+}
+
+fn check_length(actual: usize, expected: usize) -> Result<(), EncodeError> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(EncodeError::WrongDataLength { expected, actual })
+    }
+}
+
+/// Encodes a whole animation in one go.
+pub fn encode(
+    canvas_width: u16,
+    canvas_height: u16,
+    frames: &[EncodeFrame],
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, EncodeError> {
+    let mut encoder = GifEncoder::new(vec![], canvas_width, canvas_height, options)?;
+    for frame in frames {
+        encoder.write_frame(frame)?;
+    }
+    encoder.finish()
+}
+
+#[wasm_bindgen(js_name = GifEncoder)]
+pub struct GifEncoderJs {
+    inner: GifEncoder<Vec<u8>>,
+}
+
+#[wasm_bindgen(js_class = GifEncoder)]
+impl GifEncoderJs {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        canvas_width: u16,
+        canvas_height: u16,
+        options: &EncodeOptions,
+    ) -> Result<GifEncoderJs, JsError> {
+        let inner = GifEncoder::new(vec![], canvas_width, canvas_height, options)?;
+        Ok(Self { inner })
+    }
+
+    /// Adds a frame covering the whole canvas, like the `imageData` of a decoded frame.
+    #[wasm_bindgen(js_name = addFrame)]
+    pub fn add_frame(
+        &mut self,
+        rgba: Box<[u8]>,
+        delay: u16,
+        disposal_method: DisposalMethod,
+    ) -> Result<(), JsError> {
+        let mut frame = EncodeFrame::rgba(
+            self.inner.canvas_width,
+            self.inner.canvas_height,
+            rgba,
+            delay,
+        );
+        frame.disposal_method = disposal_method;
+        Ok(self.inner.write_frame(&frame)?)
+    }
+
+    /// Adds a frame from `decodeIndexed` without requantizing it.
+    #[wasm_bindgen(js_name = addIndexedFrame)]
+    pub fn add_indexed_frame(&mut self, frame: &IndexedFrame) -> Result<(), JsError> {
+        Ok(self.inner.write_frame(&frame.clone().into())?)
+    }
+
+    /// Writes the trailer and returns the finished file.
+    pub fn finish(self) -> Result<Box<[u8]>, JsError> {
+        Ok(self.inner.finish()?.into())
+    }
+}
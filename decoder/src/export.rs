@@ -0,0 +1,343 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use crate::encoder::{EncodeError, EncodeFrame, EncodeOptions, FramePixels, GifEncoder};
+use crate::{decode, DecodeOptions, Decoder, DisposalMethod, FrameStream};
+
+/// Browsers show frames with a delay below this many centiseconds for [`BROWSER_DEFAULT_DELAY`]
+const BROWSER_MIN_DELAY: u16 = 2;
+const BROWSER_DEFAULT_DELAY: u16 = 10;
+
+/// How long browsers actually show a frame with `delay`, in centiseconds.
+pub(crate) fn browser_delay(delay: u16) -> u16 {
+    if delay < BROWSER_MIN_DELAY {
+        BROWSER_DEFAULT_DELAY
+    } else {
+        delay
+    }
+}
+
+#[wasm_bindgen(js_name = exportRange)]
+pub fn export_range_js(
+    data: Box<[u8]>,
+    start: usize,
+    end: usize,
+    options: &EncodeOptions,
+) -> Result<Box<[u8]>, JsError> {
+    Ok(export_range(data, start, end, options)?.into())
+}
+
+/// Writes frames `start..end` of a GIF as a new GIF.
+///
+/// The first frame is rebuilt from the composited canvas, since the frames it was drawn over are
+/// left out. Later frames are copied over with their original palettes and rectangles, unless
+/// what they're drawn over differs from the original, in which case they're rebuilt too.
+/// Rebuilt frames keep their original palette if it has every color they need.
+pub fn export_range(
+    data: Box<[u8]>,
+    start: usize,
+    end: usize,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, EncodeError> {
+    let decode_options = DecodeOptions::default();
+    let mut composited = FrameStream::with_options(io::Cursor::new(data.clone()), &decode_options)?;
+    let mut indexed = Decoder::new(io::Cursor::new(data), decode_options)?;
+
+    let canvas_width = composited.canvas_width();
+    let canvas_height = composited.canvas_height();
+    let mut encoder = GifEncoder::new(vec![], canvas_width, canvas_height, options)?;
+
+    // Whether the exported canvas matches the original one before the current frame
+    let mut synced = false;
+    let mut written = 0;
+    for i in 0..end {
+        let (Some(frame), Some(raw)) = (
+            composited.next().transpose()?,
+            indexed.next_indexed_frame()?,
+        ) else {
+            break;
+        };
+        if i < start {
+            continue;
+        }
+
+        let right = usize::from(raw.left) + usize::from(raw.width);
+        let bottom = usize::from(raw.top) + usize::from(raw.height);
+        let fits = right <= canvas_width.into() && bottom <= canvas_height.into();
+
+        written += 1;
+        if synced && fits {
+            encoder.write_frame(&raw.into())?;
+            continue;
+        }
+
+        // Rebuilt frames cover the whole canvas, so restoring the background clears all of it
+        // and anything else has to be rebuilt after it too
+        synced = frame.disposal_method == DisposalMethod::Keep;
+        let disposal_method = if synced {
+            DisposalMethod::Keep
+        } else {
+            DisposalMethod::RestoreBackground
+        };
+
+        let pixels = match index_with_palette(&frame.image_data, &raw.palette, raw.transparent) {
+            Some(indices) => FramePixels::Indexed {
+                indices,
+                palette: raw.palette,
+                transparent: raw.transparent,
+            },
+            None => FramePixels::Rgba(frame.image_data),
+        };
+        encoder.write_frame(&EncodeFrame {
+            width: canvas_width,
+            height: canvas_height,
+            top: 0,
+            left: 0,
+            delay: frame.delay,
+            disposal_method,
+            user_input: frame.user_input,
+            pixels,
+        })?;
+    }
+
+    if written == 0 {
+        return Err(EncodeError::InvalidRange { start, end });
+    }
+    encoder.finish()
+}
+
+/// Looks up each pixel of a composited frame in `palette`, if all of them are in it.
+fn index_with_palette(rgba: &[u8], palette: &[u8], transparent: Option<u8>) -> Option<Box<[u8]>> {
+    let mut lookup = HashMap::new();
+    // Inserted backwards so the lowest index wins when a color appears twice
+    for (i, color) in palette.chunks_exact(3).enumerate().rev() {
+        if Some(i) != transparent.map(usize::from) {
+            // Palettes have at most 256 entries
+            lookup.insert(color, i as u8);
+        }
+    }
+
+    rgba.chunks_exact(4)
+        .map(|px| {
+            if px[3] == 0 {
+                transparent
+            } else {
+                lookup.get(&px[..3]).copied()
+            }
+        })
+        .collect()
+}
+
+/// The order [`export_retimed`] writes frames in.
+#[wasm_bindgen]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOrder {
+    #[default]
+    Forward,
+    Reverse,
+    /// Forward and then back again, without repeating the first and last frames, so it loops
+    /// smoothly
+    PingPong,
+}
+
+/// How [`export_retimed`] changes playback.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct RetimeOptions {
+    /// Playback speed, where 2 is twice as fast
+    pub speed: f64,
+    pub order: FrameOrder,
+    /// Frames that would be shown for fewer centiseconds than this are merged into the next
+    /// one. Browsers slow shorter delays down to 10cs, so this defaults to 2.
+    #[wasm_bindgen(js_name = minDelay)]
+    pub min_delay: u16,
+}
+
+impl Default for RetimeOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            order: FrameOrder::Forward,
+            min_delay: BROWSER_MIN_DELAY,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl RetimeOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[wasm_bindgen(js_name = exportRetimed)]
+pub fn export_retimed_js(
+    data: Box<[u8]>,
+    retime: &RetimeOptions,
+    options: &EncodeOptions,
+) -> Result<Box<[u8]>, JsError> {
+    Ok(export_retimed(data, retime, options)?.into())
+}
+
+/// Writes a GIF played back at a different speed or in a different order.
+///
+/// Delays are taken as browsers show them, then scaled. Frame start times are rounded rather
+/// than each delay, so rounding errors don't add up over a long animation. Every frame is
+/// rebuilt from the composited canvas and stored as the rectangle that changed, with disposal
+/// methods chosen so the new order composites correctly.
+pub fn export_retimed(
+    data: Box<[u8]>,
+    retime: &RetimeOptions,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, EncodeError> {
+    if !(retime.speed.is_finite() && retime.speed > 0.0) {
+        return Err(EncodeError::InvalidSpeed(retime.speed));
+    }
+
+    let gif = decode(data)?;
+    let n = gif.num_frames;
+    let order: Vec<usize> = match retime.order {
+        FrameOrder::Forward => (0..n).collect(),
+        FrameOrder::Reverse => (0..n).rev().collect(),
+        FrameOrder::PingPong => (0..n).chain((1..n.saturating_sub(1)).rev()).collect(),
+    };
+
+    // Pick the frames that are shown long enough, and for how long
+    let mut timed = vec![];
+    let mut elapsed = 0.0;
+    let mut shown_until = 0;
+    for (k, &i) in order.iter().enumerate() {
+        let delay = browser_delay(gif.delay(i).unwrap_or_default());
+        elapsed += f64::from(delay) / retime.speed;
+
+        // Saturates rather than wrapping for absurdly slow speeds
+        let end = elapsed.round() as u64;
+        let duration = end - shown_until;
+        let is_last = k + 1 == order.len();
+        if duration < retime.min_delay.into() && !is_last {
+            continue;
+        }
+
+        shown_until = end;
+        let duration = duration.clamp(retime.min_delay.into(), u16::MAX.into());
+        // Clamped to fit above
+        timed.push((i, duration as u16));
+    }
+
+    let width = usize::from(gif.canvas_width);
+    let height = usize::from(gif.canvas_height);
+    let mut encoder = GifEncoder::new(vec![], gif.canvas_width, gif.canvas_height, options)?;
+
+    // What a viewer shows before the current frame is drawn
+    let mut shown = vec![0; width * height * 4];
+    let mut next = match timed.first() {
+        Some(&(i, _)) => Some(gif.get(i)?),
+        None => None,
+    };
+    for (k, &(_, delay)) in timed.iter().enumerate() {
+        let Some(frame) = next.take() else { break };
+        next = match timed.get(k + 1) {
+            Some(&(i, _)) => Some(gif.get(i)?),
+            None => None,
+        };
+        let cur = &frame.image_data;
+
+        let changed = bounding_box(width, height, |p| cur[p..p + 4] != shown[p..p + 4]);
+        // Pixels the next frame shows as transparent have to be cleared by disposing this one
+        let cleared = next.as_ref().and_then(|next| {
+            bounding_box(width, height, |p| {
+                cur[p + 3] != 0 && next.image_data[p + 3] == 0
+            })
+        });
+        let disposal_method = if cleared.is_some() {
+            DisposalMethod::RestoreBackground
+        } else {
+            DisposalMethod::Keep
+        };
+
+        // Frames need at least one pixel, even if nothing changed, unless the canvas has none
+        let (x0, y0, x1, y1) = match (changed, cleared) {
+            (Some(a), Some(b)) => (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)),
+            (Some(r), None) | (None, Some(r)) => r,
+            (None, None) if width == 0 || height == 0 => (0, 0, 0, 0),
+            (None, None) => (0, 0, 1, 1),
+        };
+
+        // Unchanged pixels are left transparent, unless that would take a palette entry that
+        // one of the frame's own colors needs
+        let mut colors = HashSet::new();
+        for y in y0..y1 {
+            let row = (y * width + x0) * 4..(y * width + x1) * 4;
+            colors.extend(cur[row].chunks_exact(4).filter(|px| px[3] != 0));
+        }
+        let skip_unchanged = colors.len() < 256;
+
+        let mut pixels = Vec::with_capacity((x1 - x0) * (y1 - y0) * 4);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = (y * width + x) * 4;
+                if skip_unchanged && cur[p..p + 4] == shown[p..p + 4] {
+                    pixels.extend([0; 4]);
+                } else {
+                    pixels.extend_from_slice(&cur[p..p + 4]);
+                }
+
+                shown[p..p + 4].copy_from_slice(if cleared.is_some() {
+                    &[0; 4]
+                } else {
+                    &cur[p..p + 4]
+                });
+            }
+        }
+
+        // There are no colors to build a palette from, so the frame gets a placeholder one
+        let pixels = if pixels.is_empty() {
+            FramePixels::Indexed {
+                indices: Box::new([]),
+                palette: Box::new([0; 3]),
+                transparent: None,
+            }
+        } else {
+            FramePixels::Rgba(pixels.into())
+        };
+
+        // The rectangle is inside the canvas, whose sides fit in a u16
+        encoder.write_frame(&EncodeFrame {
+            width: (x1 - x0) as u16,
+            height: (y1 - y0) as u16,
+            top: y0 as u16,
+            left: x0 as u16,
+            delay,
+            disposal_method,
+            // Frames merged into this one are never shown, so only its own flag matters
+            user_input: frame.user_input,
+            pixels,
+        })?;
+    }
+
+    encoder.finish()
+}
+
+/// The smallest rectangle holding every pixel matching `pred`, which is given byte offsets into
+/// an RGBA canvas. Returned as `(left, top, right, bottom)` with exclusive ends.
+fn bounding_box(
+    width: usize,
+    height: usize,
+    pred: impl Fn(usize) -> bool,
+) -> Option<(usize, usize, usize, usize)> {
+    let mut bbox: Option<(usize, usize, usize, usize)> = None;
+    for y in 0..height {
+        for x in 0..width {
+            if pred((y * width + x) * 4) {
+                bbox = Some(match bbox {
+                    Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)),
+                    None => (x, y, x + 1, y + 1),
+                });
+            }
+        }
+    }
+    bbox
+}
@@ -0,0 +1,95 @@
+use byteorder::{ByteOrder, LE};
+
+use crate::WarningKind;
+
+/// The contents of an application extension, parsed if the application is a known one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApplicationExtension {
+    /// NETSCAPE2.0, or ANIMEXTS1.0 which has the same layout. Either sub-block can be missing.
+    Animation {
+        animexts: bool,
+        /// Number of times to repeat the animation, where 0 means forever
+        loops: Option<u16>,
+        /// How many bytes to buffer before starting playback
+        buffer_size: Option<u32>,
+    },
+    /// An XMP metadata packet
+    Xmp(String),
+    /// An ICC color profile
+    IccProfile(Vec<u8>),
+    /// Any other application's data, with its sub-blocks joined together
+    Unknown {
+        identifier: [u8; 8],
+        auth_code: [u8; 3],
+        data: Vec<u8>,
+    },
+}
+
+impl ApplicationExtension {
+    /// Parses the extension's sub-blocks, where the first is the application identifier and
+    /// auth code. Returns a warning alongside it if some of the data had to be ignored.
+    pub(crate) fn parse(blocks: &[Vec<u8>]) -> Option<(Self, Option<WarningKind>)> {
+        let (app, data) = blocks.split_first()?;
+        let app: &[u8; 11] = app.as_slice().try_into().ok()?;
+
+        let ext = match app {
+            b"NETSCAPE2.0" | b"ANIMEXTS1.0" => return Some(parse_animation(app, data)),
+            b"XMP DataXMP" => {
+                let raw = raw_data(data);
+                Self::Xmp(String::from_utf8_lossy(strip_magic_trailer(&raw)).into_owned())
+            }
+            b"ICCRGBG1012" => Self::IccProfile(data.concat()),
+            _ => Self::Unknown {
+                identifier: app[..8].try_into().unwrap(),
+                auth_code: app[8..].try_into().unwrap(),
+                data: data.concat(),
+            },
+        };
+        Some((ext, None))
+    }
+}
+
+fn parse_animation(
+    app: &[u8; 11],
+    data: &[Vec<u8>],
+) -> (ApplicationExtension, Option<WarningKind>) {
+    let mut loops = None;
+    let mut buffer_size = None;
+    let mut malformed = data.is_empty();
+
+    for block in data {
+        match block.as_slice() {
+            [1, rest @ ..] if rest.len() >= 2 => loops = Some(LE::read_u16(rest)),
+            [2, rest @ ..] if rest.len() >= 4 => buffer_size = Some(LE::read_u32(rest)),
+            _ => malformed = true,
+        }
+    }
+
+    let ext = ApplicationExtension::Animation {
+        animexts: app == b"ANIMEXTS1.0",
+        loops,
+        buffer_size,
+    };
+    (
+        ext,
+        malformed.then_some(WarningKind::MalformedNetscapeExtension),
+    )
+}
+
+/// XMP packets aren't split into sub-blocks. The raw text is written as-is, so every byte that
+/// gets read as a sub-block length is actually part of the packet. This puts them back.
+fn raw_data(data: &[Vec<u8>]) -> Vec<u8> {
+    let mut raw = vec![];
+    for block in data {
+        raw.push(block.len() as u8);
+        raw.extend(block);
+    }
+    raw
+}
+
+/// The packet is followed by bytes 0x01, 0xff, 0xfe, ..., 0x00 and then the terminator, so that
+/// a decoder reading it as sub-blocks lands on the terminator wherever it starts.
+fn strip_magic_trailer(raw: &[u8]) -> &[u8] {
+    let trailer: Vec<u8> = [1].into_iter().chain((0..=255).rev()).collect();
+    raw.strip_suffix(trailer.as_slice()).unwrap_or(raw)
+}
@@ -0,0 +1,177 @@
+use byteorder::{ByteOrder, BE};
+
+use crate::{Color, ColorTable};
+
+/// Converts colors from an embedded ICC profile's RGB space to sRGB. Only matrix/TRC profiles
+/// are supported, which covers most RGB display profiles.
+pub(crate) struct ColorTransform {
+    /// Each channel's tone curve, already applied to every possible 8-bit value
+    linear: [[f64; 256]; 3],
+    /// From the profile's linear RGB to linear sRGB, through the D50 connection space
+    matrix: [[f64; 3]; 3],
+}
+
+/// From D50 XYZ to linear sRGB, including the Bradford adaptation from D50 to D65
+const XYZ_D50_TO_SRGB: [[f64; 3]; 3] = [
+    [3.1338561, -1.6168667, -0.4906146],
+    [-0.9787684, 1.9161415, 0.0334540],
+    [0.0719453, -0.2289914, 1.4052427],
+];
+
+impl ColorTransform {
+    /// Returns `None` if the profile is malformed, or isn't an RGB matrix/TRC profile.
+    pub fn from_icc(profile: &[u8]) -> Option<Self> {
+        let header = profile.get(..132)?;
+        if &header[16..20] != b"RGB " || &header[20..24] != b"XYZ " {
+            return None;
+        }
+
+        let colorants = [b"rXYZ", b"gXYZ", b"bXYZ"].map(|sig| read_xyz(find_tag(profile, sig)?));
+        let curves = [b"rTRC", b"gTRC", b"bTRC"].map(|sig| Curve::read(find_tag(profile, sig)?));
+        let [Some(r), Some(g), Some(b)] = colorants else {
+            return None;
+        };
+        let [Some(r_curve), Some(g_curve), Some(b_curve)] = curves else {
+            return None;
+        };
+
+        // The colorants are the columns of the profile's RGB to XYZ matrix
+        let to_xyz = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+        let matrix = multiply(&XYZ_D50_TO_SRGB, &to_xyz);
+
+        let linear = [r_curve, g_curve, b_curve]
+            .map(|curve| std::array::from_fn(|i| curve.eval(i as f64 / 255.0)));
+
+        Some(Self { linear, matrix })
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        if color.is_transparent() {
+            return color;
+        }
+
+        let rgb = [color.0, color.1, color.2];
+        let linear: [f64; 3] = std::array::from_fn(|c| self.linear[c][usize::from(rgb[c])]);
+        let [r, g, b] = self.matrix.map(|row| {
+            let value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+            (srgb_encode(value.clamp(0.0, 1.0)) * 255.0).round() as u8
+        });
+        Color::rgb(r, g, b)
+    }
+
+    pub fn apply_table(&self, table: &mut ColorTable) {
+        for color in &mut table.table {
+            *color = self.apply(*color);
+        }
+    }
+}
+
+/// Looks up a tag's data in the tag table that follows the 128-byte header.
+fn find_tag<'a>(profile: &'a [u8], sig: &[u8; 4]) -> Option<&'a [u8]> {
+    let count = BE::read_u32(profile.get(128..132)?) as usize;
+    let table = profile.get(132..132 + count.checked_mul(12)?)?;
+
+    let entry = table.chunks_exact(12).find(|entry| &entry[..4] == sig)?;
+    let offset = BE::read_u32(&entry[4..8]) as usize;
+    let size = BE::read_u32(&entry[8..12]) as usize;
+    profile.get(offset..offset.checked_add(size)?)
+}
+
+fn read_xyz(tag: &[u8]) -> Option<[f64; 3]> {
+    if tag.get(..4)? != b"XYZ " {
+        return None;
+    }
+    let values = tag.get(8..20)?;
+    Some(std::array::from_fn(|i| s15_fixed16(&values[i * 4..])))
+}
+
+fn s15_fixed16(buf: &[u8]) -> f64 {
+    BE::read_i32(buf) as f64 / 65536.0
+}
+
+enum Curve {
+    Gamma(f64),
+    Table(Vec<f64>),
+    /// One of the parametric curve functions, with parameters g, a, b, c, d, e, f. Missing
+    /// parameters are filled in so that every function type can be evaluated the same way.
+    Parametric([f64; 7]),
+}
+
+impl Curve {
+    fn read(tag: &[u8]) -> Option<Self> {
+        match tag.get(..4)? {
+            b"curv" => {
+                let count = BE::read_u32(tag.get(8..12)?) as usize;
+                let entries = tag.get(12..12 + count.checked_mul(2)?)?;
+                match count {
+                    0 => Some(Self::Gamma(1.0)),
+                    1 => Some(Self::Gamma(f64::from(BE::read_u16(entries)) / 256.0)),
+                    _ => Some(Self::Table(
+                        entries
+                            .chunks_exact(2)
+                            .map(|v| f64::from(BE::read_u16(v)) / 65535.0)
+                            .collect(),
+                    )),
+                }
+            }
+            b"para" => {
+                let function = BE::read_u16(tag.get(8..10)?);
+                let count = match function {
+                    0 => 1,
+                    1 => 3,
+                    2 => 4,
+                    3 => 5,
+                    4 => 7,
+                    _ => return None,
+                };
+                let values = tag.get(12..12 + count * 4)?;
+                let p: Vec<f64> = values.chunks_exact(4).map(s15_fixed16).collect();
+
+                // Express every type as type 4: Y = (aX + b)^g + e if X >= d, otherwise cX + f
+                let params = match function {
+                    0 => [p[0], 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                    1 => [p[0], p[1], p[2], 0.0, -p[2] / p[1], 0.0, 0.0],
+                    2 => [p[0], p[1], p[2], 0.0, -p[2] / p[1], p[3], p[3]],
+                    3 => [p[0], p[1], p[2], p[3], p[4], 0.0, 0.0],
+                    _ => [p[0], p[1], p[2], p[3], p[4], p[5], p[6]],
+                };
+                Some(Self::Parametric(params))
+            }
+            _ => None,
+        }
+    }
+
+    fn eval(&self, x: f64) -> f64 {
+        let y = match self {
+            Self::Gamma(gamma) => x.powf(*gamma),
+            Self::Table(table) => {
+                let pos = x * (table.len() - 1) as f64;
+                let i = (pos as usize).min(table.len() - 2);
+                let t = pos - i as f64;
+                table[i] + (table[i + 1] - table[i]) * t
+            }
+            Self::Parametric([g, a, b, c, d, e, f]) => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        };
+        y.clamp(0.0, 1.0)
+    }
+}
+
+fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn multiply(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    std::array::from_fn(|row| {
+        std::array::from_fn(|col| (0..3).map(|k| a[row][k] * b[k][col]).sum())
+    })
+}
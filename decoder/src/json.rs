@@ -0,0 +1,22 @@
+//! JSON output shared with the command-line tool, which includes this file as a module of its
+//! own.
+
+use std::fmt::Write;
+
+/// Quotes `s` as a JSON string, escaping anything that needs it.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
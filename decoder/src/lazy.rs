@@ -0,0 +1,229 @@
+use std::cell::RefCell;
+use std::io;
+use std::mem;
+
+use byteorder::ReadBytesExt;
+
+use crate::{
+    read_blocks, Block, Compositor, DecodeError, Decoder, DisposalMethod, FrameDecoder, FrameDelta,
+    GifFrame,
+};
+
+/// Where a frame is in the file, and what's needed to decode it without the blocks before it.
+pub(crate) struct FrameEntry {
+    /// The block the frame comes from: 0x2c for an image, or 0x21 for plain text
+    sigil: u8,
+    /// Offset of the block, just after the sigil
+    offset: u64,
+    /// Length of the block, not including the sigil
+    len: u64,
+    /// How many frames come before it
+    index: usize,
+
+    delay: u16,
+    disposal_method: DisposalMethod,
+    transparency_idx: Option<usize>,
+    user_input: bool,
+
+    /// Index of the nearest frame at or before this one that can be composited onto an empty
+    /// canvas without changing the result
+    keyframe: usize,
+}
+
+impl Decoder<io::Cursor<Box<[u8]>>> {
+    /// Reads every block up to the trailer, but only records where each frame's image data is
+    /// instead of decompressing it.
+    pub(crate) fn scan_frames(&mut self) -> Result<Vec<FrameEntry>, DecodeError> {
+        let canvas_width = self.canvas_width;
+        let canvas_height = self.canvas_height;
+
+        let blank = self.compositor();
+        let mut entries: Vec<FrameEntry> = vec![];
+        // Whether the working canvas is known to be fully transparent
+        let mut clear = true;
+
+        while let Some(sigil) = self.read_sigil()? {
+            let offset = self.rdr.position();
+            let fdec = match self.read_block_with(sigil, Self::skip_frame) {
+                Ok(Block::Image(fdec)) => Some(fdec),
+                Ok(Block::Text(text)) => Some(FrameDecoder::from(&text)),
+                Ok(Block::Extension(_)) => None,
+                Ok(Block::Skipped) => continue,
+                Ok(Block::Trailer) => break,
+                Err(e) => {
+                    // Nothing after a truncated block can be read
+                    self.recover(e)?;
+                    break;
+                }
+            };
+
+            if let Some(fdec) = fdec {
+                let covers_canvas = fdec.left == 0
+                    && fdec.top == 0
+                    && fdec.width >= canvas_width
+                    && fdec.height >= canvas_height;
+
+                // A frame is independent if the canvas under it is empty, or if it paints
+                // over the whole canvas and doesn't bring the old contents back afterwards
+                let independent = clear
+                    || (covers_canvas
+                        && fdec.transparency_idx.is_none()
+                        && !matches!(fdec.disposal_method, DisposalMethod::RestorePrevious));
+
+                clear = match fdec.disposal_method {
+                    DisposalMethod::Keep => false,
+                    DisposalMethod::RestoreBackground => {
+                        (clear || covers_canvas)
+                            && blank.clears_to_transparent(fdec.transparency_idx)
+                    }
+                    DisposalMethod::RestorePrevious => clear,
+                };
+
+                let keyframe = match entries.last() {
+                    Some(prev) if !independent => prev.keyframe,
+                    _ => entries.len(),
+                };
+
+                entries.push(FrameEntry {
+                    sigil,
+                    offset,
+                    len: self.rdr.position() - offset,
+                    index: entries.len(),
+                    delay: fdec.delay,
+                    disposal_method: fdec.disposal_method,
+                    transparency_idx: fdec.transparency_idx,
+                    user_input: fdec.user_input,
+                    keyframe,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Reads a frame's image descriptor and skips over its image data. In recovery mode, a
+    /// frame whose data is cut off still counts, since some of it can be decoded.
+    fn skip_frame(&mut self) -> Result<FrameDecoder, DecodeError> {
+        self.read_image_descriptor()?;
+
+        let data = self
+            .rdr
+            .read_u8() // LZW minimum code size
+            .map_err(DecodeError::from)
+            .and_then(|_| read_blocks(&mut self.rdr));
+        if let Err(e) = data {
+            self.recover(e)?;
+        }
+
+        self.frame_index += 1;
+        Ok(mem::take(&mut self.frame_dec))
+    }
+
+    fn read_frame_at(&mut self, entry: &FrameEntry) -> Result<FrameDelta, DecodeError> {
+        self.rdr.set_position(entry.offset);
+        // Limits are checked against the frame's place in the file, like when it was scanned
+        self.frame_index = entry.index;
+        self.frame_dec = FrameDecoder {
+            delay: entry.delay,
+            disposal_method: entry.disposal_method,
+            transparency_idx: entry.transparency_idx,
+            user_input: entry.user_input,
+            ..Default::default()
+        };
+
+        let (Block::Image(delta) | Block::Text(delta)) = self.read_block(entry.sigil)? else {
+            unreachable!("block was a frame when the file was scanned");
+        };
+        debug_assert_eq!(self.rdr.position(), entry.offset + entry.len);
+
+        // Anything wrong with the frame was already reported when the file was scanned, or
+        // would be reported again every time the frame is requested
+        self.warnings.clear();
+        Ok(delta)
+    }
+}
+
+/// Just the parts of a frame's header that affect compositing.
+impl From<&FrameDelta> for FrameDecoder {
+    fn from(delta: &FrameDelta) -> Self {
+        Self {
+            left: delta.left,
+            top: delta.top,
+            width: delta.width,
+            height: delta.height,
+            delay: delta.delay,
+            disposal_method: delta.disposal_method,
+            transparency_idx: delta.transparency_idx,
+            user_input: delta.user_input,
+            ..Default::default()
+        }
+    }
+}
+
+/// Frames that are decoded from the original file when they're requested.
+pub(crate) struct LazyFrames {
+    decoder: RefCell<Decoder<io::Cursor<Box<[u8]>>>>,
+    entries: Vec<FrameEntry>,
+
+    /// The most recently requested frame, and the working canvas after it. Makes playing the
+    /// frames in order about as cheap as decoding them up front.
+    last: RefCell<Option<(usize, Compositor)>>,
+}
+
+impl LazyFrames {
+    pub fn new(decoder: Decoder<io::Cursor<Box<[u8]>>>, entries: Vec<FrameEntry>) -> Self {
+        Self {
+            decoder: RefCell::new(decoder),
+            entries,
+            last: RefCell::new(None),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn canvas_size(&self) -> (usize, usize) {
+        let decoder = self.decoder.borrow();
+        (decoder.canvas_width.into(), decoder.canvas_height.into())
+    }
+
+    /// Decodes frame `i` without compositing it.
+    pub fn delta(&self, i: usize) -> Result<FrameDelta, DecodeError> {
+        let entry = self
+            .entries
+            .get(i)
+            .ok_or(DecodeError::FrameOutOfBounds(i))?;
+        self.decoder.borrow_mut().read_frame_at(entry)
+    }
+
+    pub fn delay(&self, i: usize) -> Option<u16> {
+        self.entries.get(i).map(|entry| entry.delay)
+    }
+
+    pub fn get(&self, i: usize) -> Result<GifFrame, DecodeError> {
+        let entry = self
+            .entries
+            .get(i)
+            .ok_or(DecodeError::FrameOutOfBounds(i))?;
+        let mut decoder = self.decoder.borrow_mut();
+
+        // Pick up from the last requested frame if it's between the keyframe and this one
+        let (start, mut compositor) = match self.last.take() {
+            Some((last, compositor)) if (entry.keyframe..i).contains(&last) => {
+                (last + 1, compositor)
+            }
+            _ => (entry.keyframe, decoder.compositor()),
+        };
+
+        for prev in &self.entries[start..i] {
+            compositor.advance(&decoder.read_frame_at(prev)?);
+        }
+
+        let delta = decoder.read_frame_at(entry)?;
+        let frame = compositor.render(&delta);
+        self.last.replace(Some((i, compositor)));
+
+        Ok(frame)
+    }
+}
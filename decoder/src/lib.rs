@@ -1,12 +1,47 @@
+use std::collections::HashMap;
 use std::io::{self, prelude::*};
+use std::mem;
 
 use byteorder::{ByteOrder, ReadBytesExt, LE};
 use thiserror::Error;
 use wasm_bindgen::prelude::*;
 
+pub use crate::encoder::{
+    encode, EncodeError, EncodeFrame, EncodeOptions, FramePixels, GifEncoder,
+};
+pub use crate::export::{export_range, export_retimed, FrameOrder, RetimeOptions};
+pub use crate::extensions::ApplicationExtension;
+use crate::icc::ColorTransform;
+pub use crate::indexed::{decode_indexed, decode_indexed_with_options, IndexedFrame, IndexedGif};
+use crate::lazy::LazyFrames;
+pub use crate::options::{CompositingPolicy, DecodeLimits, DecodeOptions, LimitKind, PixelFormat};
+pub use crate::push::PushDecoder;
+pub use crate::sprites::SpriteSheet;
+use crate::store::FrameStore;
+pub use crate::structure::{read_structure, BlockKind, GifBlock, GifStructure};
+use crate::text::PlainText;
 use crate::util::{LZWError, LZWIterator};
-
+pub use crate::warning::{DecodeWarning, WarningKind};
+
+mod apng;
+mod deflate;
+mod encoder;
+mod export;
+mod extensions;
+mod icc;
+mod indexed;
+mod json;
+mod lazy;
+mod options;
+mod png;
+mod push;
+mod quantize;
+mod sprites;
+mod store;
+mod structure;
+mod text;
 mod util;
+mod warning;
 
 #[wasm_bindgen(js_name = decode)]
 pub fn decode_js(data: Box<[u8]>) -> Result<DecodedGif, JsError> {
@@ -14,10 +49,240 @@ pub fn decode_js(data: Box<[u8]>) -> Result<DecodedGif, JsError> {
 }
 
 pub fn decode(data: Box<[u8]>) -> Result<DecodedGif, DecodeError> {
+    decode_with_options(data, &DecodeOptions::default())
+}
+
+#[wasm_bindgen(js_name = decodeWithOptions)]
+pub fn decode_with_options_js(
+    data: Box<[u8]>,
+    options: &DecodeOptions,
+) -> Result<DecodedGif, JsError> {
+    Ok(decode_with_options(data, options)?)
+}
+
+pub fn decode_with_options(
+    data: Box<[u8]>,
+    options: &DecodeOptions,
+) -> Result<DecodedGif, DecodeError> {
     let cursor = io::Cursor::new(data);
-    let mut decoder = Decoder::new(cursor)?;
-    decoder.read_body()?;
-    Ok(decoder.into_gif())
+    let mut decoder = Decoder::new(cursor, *options)?;
+
+    // Created at the first frame, after any color profile has been read
+    let mut frames = None;
+    while let Some(delta) = decoder.next_frame()? {
+        let store = frames.get_or_insert_with(|| FrameStore::new(decoder.compositor()));
+        options
+            .limits
+            .check(LimitKind::OutputBytes, store.bytes_with(&delta))?;
+        store.push(delta);
+    }
+
+    let frames = frames.unwrap_or_else(|| FrameStore::new(decoder.compositor()));
+    Ok(decoder.into_gif(Frames::Stored(frames)))
+}
+
+#[wasm_bindgen(js_name = decodeLazy)]
+pub fn decode_lazy_js(data: Box<[u8]>) -> Result<DecodedGif, JsError> {
+    Ok(decode_lazy(data)?)
+}
+
+/// Only scans the structure of the file up front. Frames are decoded when they're requested,
+/// starting from the nearest frame that doesn't depend on the ones before it.
+///
+/// Errors in a frame's image data won't be found until that frame is requested.
+pub fn decode_lazy(data: Box<[u8]>) -> Result<DecodedGif, DecodeError> {
+    decode_lazy_with_options(data, &DecodeOptions::default())
+}
+
+#[wasm_bindgen(js_name = decodeLazyWithOptions)]
+pub fn decode_lazy_with_options_js(
+    data: Box<[u8]>,
+    options: &DecodeOptions,
+) -> Result<DecodedGif, JsError> {
+    Ok(decode_lazy_with_options(data, options)?)
+}
+
+pub fn decode_lazy_with_options(
+    data: Box<[u8]>,
+    options: &DecodeOptions,
+) -> Result<DecodedGif, DecodeError> {
+    let cursor = io::Cursor::new(data);
+    let mut decoder = Decoder::new(cursor, *options)?;
+    let entries = decoder.scan_frames()?;
+
+    let (canvas_width, canvas_height) = decoder.output_size();
+    let pixel_aspect_ratio = decoder.pixel_aspect_ratio;
+    let max_loops = decoder.max_loops;
+    let bg_color = decoder.bg_color;
+    let global_palette = decoder.global_palette.as_ref().map(ColorTable::to_rgb);
+    let pixel_format = decoder.options.pixel_format;
+    let limits = decoder.options.limits;
+    let comments = mem::take(&mut decoder.comments);
+    let application_extensions = mem::take(&mut decoder.app_extensions);
+    let warnings = mem::take(&mut decoder.warnings);
+
+    let frames = LazyFrames::new(decoder, entries);
+    Ok(DecodedGif {
+        canvas_width,
+        canvas_height,
+        pixel_aspect_ratio,
+        max_loops,
+        bg_color: bg_color.to_css_string(),
+        global_palette,
+        num_frames: frames.len(),
+        frames: Frames::Lazy(Box::new(frames)),
+        pixel_format,
+        limits,
+        comments,
+        application_extensions,
+        warnings,
+        pinned: HashMap::new(),
+    })
+}
+
+/// Decodes a GIF one frame at a time, instead of reading the whole file up front.
+///
+/// Each call to `next()` reads blocks until the next image descriptor has been decoded and
+/// composited, so only the current working canvas is kept in memory.
+pub struct FrameStream<R: Read> {
+    decoder: Decoder<R>,
+    // Created at the first frame, after any color profile has been read
+    compositor: Option<Compositor>,
+    finished: bool,
+}
+
+impl<R: Read> FrameStream<R> {
+    /// Reads the header and logical screen descriptor. No frames are decoded yet.
+    pub fn new(rdr: R) -> Result<Self, DecodeError> {
+        Self::with_options(rdr, &DecodeOptions::default())
+    }
+
+    pub fn with_options(rdr: R, options: &DecodeOptions) -> Result<Self, DecodeError> {
+        let decoder = Decoder::new(rdr, *options)?;
+        Ok(Self {
+            decoder,
+            compositor: None,
+            finished: false,
+        })
+    }
+
+    pub fn canvas_width(&self) -> u16 {
+        self.decoder.output_size().0
+    }
+
+    pub fn canvas_height(&self) -> u16 {
+        self.decoder.output_size().1
+    }
+
+    pub fn pixel_aspect_ratio(&self) -> Option<f32> {
+        self.decoder.pixel_aspect_ratio
+    }
+
+    /// The loop count from the NETSCAPE2.0 extension. This is usually found before the first
+    /// frame, but can technically appear anywhere in the file.
+    pub fn max_loops(&self) -> Option<u16> {
+        self.decoder.max_loops
+    }
+
+    pub fn bg_color(&self) -> String {
+        self.decoder.bg_color.to_css_string()
+    }
+
+    /// Comment extensions read so far.
+    pub fn comments(&self) -> &[GifComment] {
+        &self.decoder.comments
+    }
+
+    /// Application extensions read so far.
+    pub fn application_extensions(&self) -> &[ApplicationExtension] {
+        &self.decoder.app_extensions
+    }
+
+    /// Quirks that have been worked around so far.
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        &self.decoder.warnings
+    }
+}
+
+impl<R: Read> Iterator for FrameStream<R> {
+    type Item = Result<GifFrame, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match self.decoder.next_frame() {
+            Ok(Some(delta)) => {
+                let decoder = &self.decoder;
+                let compositor = self.compositor.get_or_insert_with(|| decoder.compositor());
+                Some(Ok(compositor.render(&delta)))
+            }
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// JS wrapper around [`FrameStream`], so the player can start showing frames before the rest of
+/// the file has been decoded.
+#[wasm_bindgen(js_name = GifStream)]
+pub struct GifStreamJs {
+    inner: FrameStream<io::Cursor<Box<[u8]>>>,
+}
+
+#[wasm_bindgen(js_class = GifStream)]
+impl GifStreamJs {
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Box<[u8]>) -> Result<GifStreamJs, JsError> {
+        Ok(Self {
+            inner: FrameStream::new(io::Cursor::new(data))?,
+        })
+    }
+
+    #[wasm_bindgen(js_name = withOptions)]
+    pub fn with_options(data: Box<[u8]>, options: &DecodeOptions) -> Result<GifStreamJs, JsError> {
+        Ok(Self {
+            inner: FrameStream::with_options(io::Cursor::new(data), options)?,
+        })
+    }
+
+    #[wasm_bindgen(getter, js_name = canvasWidth)]
+    pub fn canvas_width(&self) -> u16 {
+        self.inner.canvas_width()
+    }
+
+    #[wasm_bindgen(getter, js_name = canvasHeight)]
+    pub fn canvas_height(&self) -> u16 {
+        self.inner.canvas_height()
+    }
+
+    #[wasm_bindgen(getter, js_name = pixelAspectRatio)]
+    pub fn pixel_aspect_ratio(&self) -> Option<f32> {
+        self.inner.pixel_aspect_ratio()
+    }
+
+    #[wasm_bindgen(getter, js_name = maxLoops)]
+    pub fn max_loops(&self) -> Option<u16> {
+        self.inner.max_loops()
+    }
+
+    #[wasm_bindgen(getter, js_name = bgColor)]
+    pub fn bg_color(&self) -> String {
+        self.inner.bg_color()
+    }
+
+    /// Returns `undefined` once the end of the file has been reached.
+    #[wasm_bindgen(js_name = nextFrame)]
+    pub fn next_frame(&mut self) -> Result<Option<GifFrame>, JsError> {
+        Ok(self.inner.next().transpose()?)
+    }
 }
 
 #[wasm_bindgen]
@@ -26,29 +291,203 @@ pub struct DecodedGif {
     pub canvas_width: u16,
     #[wasm_bindgen(readonly, js_name = canvasHeight)]
     pub canvas_height: u16,
+    /// Width of a pixel divided by its height, if the file specifies one. Frames are only
+    /// stretched to match if [`DecodeOptions::square_pixels`] is set.
+    #[wasm_bindgen(readonly, js_name = pixelAspectRatio)]
+    pub pixel_aspect_ratio: Option<f32>,
     #[wasm_bindgen(readonly, js_name = maxLoops)]
     pub max_loops: Option<u16>,
 
     #[wasm_bindgen(readonly, getter_with_clone, js_name = bgColor)]
     pub bg_color: String,
+    /// RGB order, `None` if the file has no global color table
+    #[wasm_bindgen(readonly, getter_with_clone, js_name = globalPalette)]
+    pub global_palette: Option<Box<[u8]>>,
 
     #[wasm_bindgen(readonly, js_name = numFrames)]
     pub num_frames: usize,
+    frames: Frames,
+    /// The layout of each frame's `image_data`
+    #[wasm_bindgen(readonly, js_name = pixelFormat)]
+    pub pixel_format: PixelFormat,
+    /// What the GIF was decoded with, which exports are held to as well
+    limits: DecodeLimits,
+
+    #[wasm_bindgen(skip)]
+    pub comments: Vec<GifComment>,
+
+    /// Every application extension in the file, in order.
     #[wasm_bindgen(skip)]
-    pub frames: Vec<GifFrame>,
+    pub application_extensions: Vec<ApplicationExtension>,
+
+    /// Quirks in the file that the decoder worked around. In lazy mode, problems with the image
+    /// data itself aren't included, since frames haven't been decoded yet.
+    #[wasm_bindgen(skip)]
+    pub warnings: Vec<DecodeWarning>,
+
+    /// RGBA pixels of frames that are being viewed without copying
+    pinned: HashMap<usize, Box<[u8]>>,
+}
+
+enum Frames {
+    Stored(FrameStore),
+    Lazy(Box<LazyFrames>),
 }
 
 #[wasm_bindgen]
 impl DecodedGif {
     pub fn frame(&self, i: usize) -> Result<GifFrame, JsError> {
-        if i >= self.frames.len() {
-            return Err(JsError::new("Index out of bounds"));
+        Ok(self.get(i)?)
+    }
+
+    /// Frame `i`'s RGBA pixels, as a view straight into wasm memory rather than a copy. The
+    /// frame stays pinned until `releaseFrame(i)` is called.
+    ///
+    /// The view is detached if wasm memory grows, which can happen on any call into the decoder,
+    /// so it should be used (or copied into an `ImageData`) right away.
+    #[wasm_bindgen(js_name = frameView)]
+    pub fn frame_view(&mut self, i: usize) -> Result<js_sys::Uint8Array, JsError> {
+        let pixels = self.pin(i)?;
+        // SAFETY: The buffer stays in `self.pinned` until it's released, and the view isn't
+        // used after memory grows, as documented above
+        Ok(unsafe { js_sys::Uint8Array::view(pixels) })
+    }
+
+    #[wasm_bindgen(js_name = releaseFrame)]
+    pub fn release_frame(&mut self, i: usize) {
+        self.release(i);
+    }
+
+    #[wasm_bindgen(getter, js_name = comments)]
+    pub fn comments_js(&self) -> Vec<GifComment> {
+        self.comments.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = xmp)]
+    pub fn xmp_js(&self) -> Option<String> {
+        self.xmp().map(str::to_owned)
+    }
+
+    #[wasm_bindgen(getter, js_name = iccProfile)]
+    pub fn icc_profile_js(&self) -> Option<Box<[u8]>> {
+        self.icc_profile().map(Box::from)
+    }
+
+    #[wasm_bindgen(getter, js_name = warnings)]
+    pub fn warning_messages(&self) -> Vec<String> {
+        self.warnings.iter().map(ToString::to_string).collect()
+    }
+}
+
+impl DecodedGif {
+    /// The first XMP metadata packet in the file.
+    pub fn xmp(&self) -> Option<&str> {
+        self.application_extensions
+            .iter()
+            .find_map(|ext| match ext {
+                ApplicationExtension::Xmp(packet) => Some(packet.as_str()),
+                _ => None,
+            })
+    }
+
+    /// The first ICC color profile in the file.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.application_extensions
+            .iter()
+            .find_map(|ext| match ext {
+                ApplicationExtension::IccProfile(profile) => Some(profile.as_slice()),
+                _ => None,
+            })
+    }
+
+    /// Composites frame `i` and keeps its RGBA pixels until [`release`] is called, so they can be
+    /// lent out repeatedly without copying.
+    ///
+    /// [`release`]: DecodedGif::release
+    pub fn pin(&mut self, i: usize) -> Result<&[u8], DecodeError> {
+        if !self.pinned.contains_key(&i) {
+            let frame = self.get(i)?;
+            self.pinned.insert(i, frame.image_data);
         }
+        Ok(&self.pinned[&i])
+    }
 
-        Ok(self.frames[i].clone())
+    /// Frees a frame pinned by [`pin`]. Returns whether it was pinned.
+    ///
+    /// [`pin`]: DecodedGif::pin
+    pub fn release(&mut self, i: usize) -> bool {
+        self.pinned.remove(&i).is_some()
+    }
+
+    /// Composites frame `i`, starting from the nearest keyframe before it.
+    pub fn get(&self, i: usize) -> Result<GifFrame, DecodeError> {
+        match &self.frames {
+            Frames::Stored(store) => store.get(i).ok_or(DecodeError::FrameOutOfBounds(i)),
+            Frames::Lazy(lazy) => lazy.get(i),
+        }
+    }
+
+    /// The size of the canvas frames are drawn on, before any stretching to square pixels.
+    pub(crate) fn native_size(&self) -> (usize, usize) {
+        match &self.frames {
+            Frames::Stored(store) => store.size(),
+            Frames::Lazy(lazy) => lazy.canvas_size(),
+        }
+    }
+
+    /// Calls `f` with frame `i` as it's stored in the file, before compositing.
+    pub(crate) fn with_delta<T>(
+        &self,
+        i: usize,
+        f: impl FnOnce(&FrameDelta) -> T,
+    ) -> Result<T, DecodeError> {
+        match &self.frames {
+            Frames::Stored(store) => store
+                .delta(i)
+                .map(f)
+                .ok_or(DecodeError::FrameOutOfBounds(i)),
+            Frames::Lazy(lazy) => Ok(f(&lazy.delta(i)?)),
+        }
+    }
+
+    /// Frame `i`'s delay, without compositing it.
+    pub(crate) fn delay(&self, i: usize) -> Option<u16> {
+        match &self.frames {
+            Frames::Stored(store) => store.delay(i),
+            Frames::Lazy(lazy) => lazy.delay(i),
+        }
+    }
+
+    /// Composites every frame in order. Cheaper than calling [`get`] for each index.
+    ///
+    /// This replaces the `frames: Vec<GifFrame>` field, which went away once frames started
+    /// being stored as deltas or decoded lazily. Code that iterated over it can iterate over
+    /// this instead, and `gif.frames().collect::<Result<Vec<_>, _>>()` gets the old vector
+    /// back. Indexing it becomes [`get`], and its length is [`num_frames`].
+    ///
+    /// [`get`]: DecodedGif::get
+    /// [`num_frames`]: DecodedGif::num_frames
+    pub fn frames(&self) -> Box<dyn Iterator<Item = Result<GifFrame, DecodeError>> + '_> {
+        match &self.frames {
+            Frames::Stored(store) => Box::new(store.iter().map(Ok)),
+            Frames::Lazy(lazy) => Box::new((0..lazy.len()).map(|i| lazy.get(i))),
+        }
     }
 }
 
+/// Text from a comment extension, which often names the tool or person that made the file.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GifComment {
+    /// Index of the frame that the comment comes before. Equal to the number of frames if
+    /// it comes after the last one.
+    #[wasm_bindgen(readonly)]
+    pub frame: usize,
+    /// Decoded as UTF-8, with invalid sequences replaced
+    #[wasm_bindgen(readonly, getter_with_clone)]
+    pub text: String,
+}
+
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct GifFrame {
@@ -63,28 +502,80 @@ pub struct GifFrame {
     #[wasm_bindgen(readonly)]
     pub delay: u16, // Note most browsers round up low frame times
 
+    #[wasm_bindgen(readonly, js_name = disposalMethod)]
+    pub disposal_method: DisposalMethod,
+    #[wasm_bindgen(readonly, js_name = transparencyIndex)]
+    pub transparency_index: Option<u8>,
+    #[wasm_bindgen(readonly)]
+    pub interlaced: bool,
+    /// Whether the frame should stay up until the user does something. See [`GifFrame::wait`].
+    #[wasm_bindgen(readonly, js_name = userInput)]
+    pub user_input: bool,
+    #[wasm_bindgen(readonly, getter_with_clone, js_name = localPalette)]
+    pub local_palette: Option<Box<[u8]>>, // RGB order, `None` if the global palette was used
+
+    /// Pixels of the whole canvas, laid out as chosen by [`DecodeOptions::pixel_format`]
     #[wasm_bindgen(readonly, getter_with_clone, js_name = imageData)]
-    pub image_data: Box<[u8]>, // RGBA order
-                               // pub image_data: ImageData,
+    pub image_data: Box<[u8]>,
 }
 
 impl GifFrame {
-    fn new(fdec: &FrameDecoder, cvs: Canvas) -> Self {
+    fn new(delta: &FrameDelta, cvs: Canvas, format: PixelFormat, matte: Color) -> Self {
+        let mut image_data = Vec::with_capacity(cvs.data.len() * format.bytes_per_pixel());
+        for color in cvs.data {
+            format.write(color, matte, &mut image_data);
+        }
+
         Self {
-            width: fdec.width,
-            height: fdec.height,
-            top: fdec.top,
-            left: fdec.left,
-            delay: fdec.delay,
-            image_data: cvs
-                .data
-                .into_iter()
-                .flat_map(|color| color.into_arr())
-                .collect(),
+            width: delta.width,
+            height: delta.height,
+            top: delta.top,
+            left: delta.left,
+            delay: delta.delay,
+            disposal_method: delta.disposal_method,
+            transparency_index: transparent_byte(delta.transparency_idx),
+            interlaced: delta.interlaced,
+            user_input: delta.user_input,
+            local_palette: delta.palette.as_ref().map(ColorTable::to_rgb),
+            image_data: image_data.into(),
+        }
+    }
+
+    /// What the player should wait for before showing the next frame.
+    pub fn wait(&self) -> FrameWait {
+        match (self.user_input, self.delay) {
+            (false, delay) => FrameWait::Delay(delay),
+            (true, 0) => FrameWait::UserInput { timeout: None },
+            (true, delay) => FrameWait::UserInput {
+                timeout: Some(delay),
+            },
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl GifFrame {
+    /// If the frame waits for user input, the delay after which to move on anyway. `undefined`
+    /// if it should wait forever, or doesn't wait for input at all.
+    #[wasm_bindgen(getter, js_name = inputTimeout)]
+    pub fn input_timeout(&self) -> Option<u16> {
+        match self.wait() {
+            FrameWait::UserInput { timeout } => timeout,
+            FrameWait::Delay(_) => None,
         }
     }
 }
 
+/// When to move on from a frame during playback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameWait {
+    /// After the delay, in hundredths of a second
+    Delay(u16),
+    /// When the user clicks or presses a key, or after the timeout (in hundredths of a second)
+    /// if there is one. The spec leaves the exact kind of input up to the player.
+    UserInput { timeout: Option<u16> },
+}
+
 #[derive(Error, Debug)]
 pub enum DecodeError {
     #[error("IO error: {0}")]
@@ -107,18 +598,26 @@ pub enum DecodeError {
 
     #[error("LZW decompression error: {0}")]
     LZWError(#[from] LZWError),
+
+    #[error("Frame index {0} is out of bounds")]
+    FrameOutOfBounds(usize),
+
+    /// The file needs more memory than [`DecodeOptions::limits`] allows
+    #[error("File goes over the limit of {max} {kind}")]
+    LimitExceeded { kind: LimitKind, max: usize },
 }
 
 #[derive(Default)]
 struct Decoder<R: Read> {
-    rdr: R,
+    rdr: PositionReader<R>,
+    options: DecodeOptions,
 
     // From logical screen descriptor
     canvas_width: u16,
     canvas_height: u16,
     global_palette: Option<ColorTable>,
-    #[allow(dead_code)]
     bg_color: Color,
+    pixel_aspect_ratio: Option<f32>,
 
     // From NETSCAPE2.0 appl. extension
     max_loops: Option<u16>,
@@ -126,12 +625,24 @@ struct Decoder<R: Read> {
     // Frame-specific state, cleared after each frame
     frame_dec: FrameDecoder,
 
-    working_canvas: Canvas,
-    frames: Vec<GifFrame>,
+    comments: Vec<GifComment>,
+    app_extensions: Vec<ApplicationExtension>,
+    // Converts palettes to sRGB, once a supported color profile has been found
+    color_profile: Option<Box<ColorProfile>>,
+    // Quirks that were worked around
+    warnings: Vec<DecodeWarning>,
+    // Offset of the block being read, and how many frames came before it
+    block_offset: u64,
+    frame_index: usize,
+    // Whether the last block was skipped for being unrecognizable
+    skipping: bool,
 }
 
 impl<R: Read> Decoder<R> {
-    fn new(mut rdr: R) -> Result<Self, DecodeError> {
+    fn new(rdr: R, options: DecodeOptions) -> Result<Self, DecodeError> {
+        let mut rdr = PositionReader::new(rdr);
+        let mut warnings = vec![];
+
         let mut magic = [0; 3];
         rdr.read_exact(&mut magic)?;
         if magic != *b"GIF" {
@@ -153,14 +664,24 @@ impl<R: Read> Decoder<R> {
         let packed = rdr.read_u8()?;
         let has_global_palette = packed & 0x80 != 0;
         let global_palette_size = 1usize << ((packed & 0x7) + 1);
-        let background_color_index = usize::from(rdr.read_u8()?);
+        let background_color_index = rdr.read_u8()?;
 
-        rdr.read_u8()?; // pixel aspect ratio -- unused
+        let pixel_aspect_ratio = match rdr.read_u8()? {
+            0 => None,
+            n => Some((f32::from(n) + 15.0) / 64.0),
+        };
 
         // Read global color table if present
         let (bg_color, global_palette) = if has_global_palette {
             let ct = ColorTable::read(&mut rdr, global_palette_size)?;
-            let bg_color = ct.get(background_color_index).unwrap_or_default();
+            let bg_color = ct.get(background_color_index.into()).unwrap_or_else(|_| {
+                warnings.push(DecodeWarning {
+                    offset: 11,
+                    frame: 0,
+                    kind: WarningKind::BackgroundColorOutOfBounds(background_color_index),
+                });
+                Color::default()
+            });
             let global_palette = Some(ct);
 
             (bg_color, global_palette)
@@ -168,95 +689,368 @@ impl<R: Read> Decoder<R> {
             (Color::default(), None)
         };
 
-        let working_canvas = Canvas::from_bg_color(
-            Color::transparent(),
-            canvas_width.into(),
-            canvas_height.into(),
-        );
-
-        Ok(Self {
+        let decoder = Self {
             rdr,
+            options,
             canvas_width,
             canvas_height,
             global_palette,
             bg_color,
+            pixel_aspect_ratio,
             max_loops: None,
             frame_dec: FrameDecoder::default(),
-            working_canvas,
-            frames: vec![],
-        })
+            comments: vec![],
+            app_extensions: vec![],
+            color_profile: None,
+            warnings,
+            block_offset: 0,
+            frame_index: 0,
+            skipping: false,
+        };
+        decoder.check_canvas_limits()?;
+        Ok(decoder)
+    }
+
+    /// Checks the canvas against the limits, before anything the size of it is allocated.
+    fn check_canvas_limits(&self) -> Result<(), DecodeError> {
+        let limits = &self.options.limits;
+        let (width, height) = self.output_size();
+        let output_pixels = usize::from(width) * usize::from(height);
+        limits.check(
+            LimitKind::CanvasPixels,
+            usize::from(self.canvas_width) * usize::from(self.canvas_height),
+        )?;
+        limits.check(LimitKind::CanvasPixels, output_pixels)?;
+
+        // Every way of decoding hands out at least one full frame
+        limits.check(
+            LimitKind::OutputBytes,
+            output_pixels * self.options.pixel_format.bytes_per_pixel(),
+        )
+    }
+
+    /// Checks a frame of `width` by `height` pixels against the limits, before its pixels are
+    /// allocated. `frame_index` has to be the number of frames before it.
+    fn check_frame_limits(&self, width: u16, height: u16) -> Result<(), DecodeError> {
+        let limits = &self.options.limits;
+        let frames = self.frame_index + 1;
+        limits.check(LimitKind::Frames, frames)?;
+        limits.check(
+            LimitKind::CanvasPixels,
+            usize::from(width) * usize::from(height),
+        )
+    }
+
+    /// An empty working canvas to composite this GIF's frames onto.
+    fn compositor(&self) -> Compositor {
+        // The background color is meaningless without a global color table, and is only
+        // converted if the color profile comes before every frame
+        let background = match &self.color_profile {
+            _ if self.global_palette.is_none() => Color::transparent(),
+            Some(profile) if profile.from_frame > 0 => profile.original_bg_color,
+            _ => self.bg_color,
+        };
+
+        let mut compositor = Compositor::new(
+            self.canvas_width.into(),
+            self.canvas_height.into(),
+            self.options.compositing,
+            background,
+        );
+        let output_size = self.output_size();
+        if output_size != (self.canvas_width, self.canvas_height) {
+            compositor.output_size = Some((output_size.0.into(), output_size.1.into()));
+        }
+        compositor.pixel_format = self.options.pixel_format;
+        compositor.matte = self.options.matte();
+        compositor
     }
 
-    fn read_body(&mut self) -> Result<(), DecodeError> {
+    /// The size of the frames that are handed out. Differs from the logical screen size if
+    /// frames are being stretched to square pixels, in which case one side is made longer.
+    fn output_size(&self) -> (u16, u16) {
+        let (width, height) = (self.canvas_width, self.canvas_height);
+        let stretch = |len: u16, by: f32| (f32::from(len) * by).round().min(65535.0) as u16;
+
+        match self.pixel_aspect_ratio {
+            Some(ratio) if self.options.square_pixels && ratio > 1.0 => {
+                (stretch(width, ratio), height)
+            }
+            Some(ratio) if self.options.square_pixels && ratio < 1.0 => {
+                (width, stretch(height, 1.0 / ratio))
+            }
+            _ => (width, height),
+        }
+    }
+
+    /// Reads blocks until a frame has been decoded. Returns `None` at the end of the file.
+    fn next_frame(&mut self) -> Result<Option<FrameDelta>, DecodeError> {
         loop {
-            let sigil = match self.rdr.read_u8() {
-                Ok(b) => b,
-                // Allow files without a trailer
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
-                Err(e) => return Err(e.into()),
+            let Some(sigil) = self.read_sigil()? else {
+                return Ok(None);
             };
 
-            match sigil {
-                0x21 => self.read_extension()?,
-                0x2c => self.read_frame()?,
-                0x3b => return Ok(()),
-                b => return Err(DecodeError::UnknownBlock(b)),
-            };
+            match self.read_block(sigil) {
+                Ok(Block::Extension(_) | Block::Skipped) => {}
+                Ok(Block::Image(frame) | Block::Text(frame)) => return Ok(Some(frame)),
+                Ok(Block::Trailer) => return Ok(None),
+                // Nothing after a truncated block can be read
+                Err(e) => {
+                    self.recover(e)?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Reads the byte that starts the next block. Returns `None` at the end of the file.
+    fn read_sigil(&mut self) -> Result<Option<u8>, DecodeError> {
+        self.block_offset = self.rdr.position();
+        match self.rdr.read_u8() {
+            Ok(b) => Ok(Some(b)),
+            // Allow files without a trailer
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.warn(WarningKind::MissingTrailer);
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn warn(&mut self, kind: WarningKind) {
+        self.warnings.push(DecodeWarning {
+            offset: self.block_offset,
+            frame: self.frame_index,
+            kind,
+        });
+    }
+
+    /// In recovery mode, records `err` as a warning so decoding can carry on. Otherwise, or if
+    /// there's no way to recover from it, returns it.
+    fn recover(&mut self, err: DecodeError) -> Result<(), DecodeError> {
+        let kind = match err {
+            _ if !self.options.recover => return Err(err),
+            DecodeError::IO(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                WarningKind::Truncated
+            }
+            DecodeError::UnknownBlock(b) => WarningKind::UnknownBlock(b),
+            DecodeError::ColorTableOutOfBounds => WarningKind::ColorTableOutOfBounds,
+            DecodeError::FrameUnderflow => WarningKind::FrameUnderflow,
+            DecodeError::FrameOverflow => WarningKind::FrameOverflow,
+            DecodeError::LZWError(e) => WarningKind::LZWError(e),
+            _ => return Err(err),
+        };
+
+        self.warn(kind);
+        Ok(())
+    }
+
+    /// Reads the block introduced by `sigil`, which has already been consumed.
+    fn read_block(&mut self, sigil: u8) -> Result<Block, DecodeError> {
+        self.read_block_with(sigil, Self::read_frame)
+    }
+
+    /// Like `read_block`, but images are read by `read_image`, so they can be decoded some other
+    /// way or skipped over.
+    fn read_block_with<T>(
+        &mut self,
+        sigil: u8,
+        read_image: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Block<T>, DecodeError> {
+        let block = match sigil {
+            0x21 => {
+                let label = self.rdr.read_u8()?;
+                match self.read_extension(label)? {
+                    Some(text) => Block::Text(text),
+                    None => Block::Extension(label),
+                }
+            }
+            0x2c => Block::Image(read_image(self)?),
+            0x3b => Block::Trailer,
+            b => {
+                self.skip_unknown(b)?;
+                return Ok(Block::Skipped);
+            }
+        };
+        self.skipping = false;
+        Ok(block)
+    }
+
+    /// There's no way to know how long an unknown block is, so in recovery mode, it's skipped
+    /// a byte at a time until something recognizable turns up. Only warns once per run of junk.
+    fn skip_unknown(&mut self, sigil: u8) -> Result<(), DecodeError> {
+        if !self.skipping {
+            self.recover(DecodeError::UnknownBlock(sigil))?;
         }
+        self.skipping = true;
+        Ok(())
     }
 
-    fn read_extension(&mut self) -> Result<(), DecodeError> {
-        match self.rdr.read_u8()? {
-            0xf9 => self.frame_dec.read_gfx_ctrl_ext(&mut self.rdr),
-            0xff => self.read_application_ext(),
+    /// Reads the rest of an extension with `label`. Returns a frame if the extension is plain
+    /// text that should be drawn.
+    fn read_extension(&mut self, label: u8) -> Result<Option<FrameDelta>, DecodeError> {
+        match label {
+            0x01 if self.options.plain_text => return self.read_plain_text(),
+            0xf9 => {
+                if let Some(kind) = self.frame_dec.read_gfx_ctrl_ext(&mut self.rdr)? {
+                    self.warn(kind);
+                }
+            }
+            0xfe => {
+                let text = read_blocks(&mut self.rdr)?.concat();
+                self.comments.push(GifComment {
+                    frame: self.frame_index,
+                    text: String::from_utf8_lossy(&text).into_owned(),
+                });
+            }
+            0xff => self.read_application_ext()?,
             _ => {
                 read_blocks(&mut self.rdr)?;
-                Ok(())
             }
         }
+        Ok(None)
+    }
+
+    /// Draws a Plain Text Extension as a frame of its own, using the graphics control
+    /// extension before it.
+    fn read_plain_text(&mut self) -> Result<Option<FrameDelta>, DecodeError> {
+        let text = PlainText::read(&mut self.rdr)?;
+        let fdec = mem::take(&mut self.frame_dec);
+
+        let Some(text) = text else {
+            self.warn(WarningKind::MalformedPlainText);
+            return Ok(None);
+        };
+        self.check_frame_limits(text.width, text.height)?;
+        // Text can only use the global color table
+        let Some(palette) = self.frame_global_palette() else {
+            self.warn(WarningKind::MissingColorTable);
+            return Ok(None);
+        };
+
+        let delta = FrameDelta {
+            left: text.left,
+            top: text.top,
+            width: text.width,
+            height: text.height,
+            delay: fdec.delay,
+            disposal_method: fdec.disposal_method,
+            transparency_idx: fdec.transparency_idx,
+            interlaced: false,
+            user_input: fdec.user_input,
+            palette: None,
+            image: text.render(palette, fdec.transparency_idx),
+        };
+        self.frame_index += 1;
+
+        Ok(Some(delta))
     }
 
     fn read_application_ext(&mut self) -> Result<(), DecodeError> {
         let blocks = read_blocks(&mut self.rdr)?;
-        if blocks.len() < 2 {
+        let Some((ext, warning)) = ApplicationExtension::parse(&blocks) else {
+            // No valid application identifier
             return Ok(());
-        }
+        };
 
-        if &blocks[0] != b"NETSCAPE2.0" {
-            return Ok(());
+        if let Some(kind) = warning {
+            self.warn(kind);
+        }
+        if let ApplicationExtension::Animation {
+            loops: Some(loops), ..
+        } = ext
+        {
+            self.max_loops = Some(loops);
         }
 
-        if blocks[1].len() < 3 {
-            return Ok(());
+        if let ApplicationExtension::IccProfile(profile) = &ext {
+            if self.options.color_management && self.color_profile.is_none() {
+                self.set_color_profile(profile);
+            }
         }
 
-        self.max_loops = Some(LE::read_u16(&blocks[1][1..3]));
+        self.app_extensions.push(ext);
         Ok(())
     }
 
-    fn read_frame(&mut self) -> Result<(), DecodeError> {
-        // Read image descriptor
+    /// Converts the global color table and background color to sRGB, along with every local
+    /// color table from here on. Frames before the profile are left alone.
+    fn set_color_profile(&mut self, profile: &[u8]) {
+        let Some(transform) = ColorTransform::from_icc(profile) else {
+            self.warn(WarningKind::UnsupportedColorProfile);
+            return;
+        };
+
+        let original_palette = self.global_palette.clone();
+        if let Some(palette) = &mut self.global_palette {
+            transform.apply_table(palette);
+        }
+        let original_bg_color = self.bg_color;
+        self.bg_color = transform.apply(self.bg_color);
+        self.color_profile = Some(Box::new(ColorProfile {
+            transform,
+            from_frame: self.frame_index,
+            original_palette,
+            original_bg_color,
+        }));
+    }
+
+    /// The color profile, if the frame being read comes after it and should be converted.
+    /// Frames can be read again out of order when decoding lazily.
+    fn frame_color_profile(&self) -> Option<&ColorProfile> {
+        self.color_profile
+            .as_deref()
+            .filter(|profile| self.frame_index >= profile.from_frame)
+    }
+
+    /// The global color table as the frame being read sees it.
+    fn frame_global_palette(&self) -> Option<&ColorTable> {
+        match &self.color_profile {
+            Some(profile) if self.frame_color_profile().is_none() => {
+                profile.original_palette.as_ref()
+            }
+            _ => self.global_palette.as_ref(),
+        }
+    }
+
+    /// Reads an image descriptor and local color table into `frame_dec`.
+    fn read_image_descriptor(&mut self) -> Result<(), DecodeError> {
         self.frame_dec.read_image_descriptor(&mut self.rdr)?;
+        self.check_frame_limits(self.frame_dec.width, self.frame_dec.height)?;
+        if let Some(mut palette) = self.frame_dec.palette.take() {
+            if let Some(profile) = self.frame_color_profile() {
+                profile.transform.apply_table(&mut palette);
+            }
+            self.frame_dec.palette = Some(palette);
+        }
 
-        // Choose color table to use
-        let palette = if let Some(pal) = &self.frame_dec.palette {
-            pal
-        } else if let Some(pal) = &self.global_palette {
-            pal
-        } else {
-            // All-black color table, just to display something
-            &ColorTable::null()
-        };
+        let fdec = &self.frame_dec;
+        let right = u32::from(fdec.left) + u32::from(fdec.width);
+        let bottom = u32::from(fdec.top) + u32::from(fdec.height);
+
+        if fdec.palette.is_none() && self.global_palette.is_none() {
+            self.warn(WarningKind::MissingColorTable);
+        }
+        if right > self.canvas_width.into() || bottom > self.canvas_height.into() {
+            self.warn(WarningKind::FrameOutsideCanvas);
+        }
+
+        Ok(())
+    }
 
-        // Read the image data, deinterlacing if necessary
+    fn read_frame(&mut self) -> Result<FrameDelta, DecodeError> {
+        let indices = self.read_frame_indices()?;
+        let width = usize::from(self.frame_dec.width);
+        let height = usize::from(self.frame_dec.height);
+        let transparency_idx = self.frame_dec.transparency_idx;
+
+        // All-black color table if there isn't one, just to display something
+        let null = ColorTable::null();
+        let palette = self.frame_palette().unwrap_or(&null);
+
+        // Convert to colors, deinterlacing if necessary
         let canvas = {
-            let c = Canvas::from_gif_data(
-                &mut self.rdr,
-                palette,
-                self.frame_dec.transparency_idx,
-                self.frame_dec.width.into(),
-                self.frame_dec.height.into(),
-            )?;
+            let c = Canvas::from_indices(&indices, palette, transparency_idx, width, height);
             if self.frame_dec.interlaced {
                 c.deinterlaced()
             } else {
@@ -264,52 +1058,258 @@ impl<R: Read> Decoder<R> {
             }
         };
 
-        // Blit image onto canvas
-        let new_canvas = self.working_canvas.blit(
-            &canvas,
-            self.frame_dec.top.into(),
-            self.frame_dec.left.into(),
-        );
+        // Construct FrameDelta and clear frame state
+        let delta = FrameDelta {
+            left: self.frame_dec.left,
+            top: self.frame_dec.top,
+            width: self.frame_dec.width,
+            height: self.frame_dec.height,
+            delay: self.frame_dec.delay,
+            disposal_method: self.frame_dec.disposal_method,
+            transparency_idx: self.frame_dec.transparency_idx,
+            interlaced: self.frame_dec.interlaced,
+            user_input: self.frame_dec.user_input,
+            palette: self.frame_dec.palette.take(),
+            image: canvas,
+        };
+        self.frame_dec = FrameDecoder::default();
+        self.frame_index += 1;
 
-        // Handle frame disposal method
-        match self.frame_dec.disposal_method {
-            DisposalMethod::Keep => self.working_canvas = new_canvas.clone(),
+        Ok(delta)
+    }
 
-            // Although the GIF specification says that this disposal method
-            // should clear the frame's area with the background color, every
-            // modern viewer/library I could find (except for PIL) clears it
-            // with transparency instead. Apparently it's de facto standard now.
-            // https://usage.imagemagick.org/anim_basics/#background
-            DisposalMethod::RestoreBackground => {
-                self.working_canvas.clear_rect_mut(
-                    self.frame_dec.top.into(),
-                    self.frame_dec.left.into(),
-                    self.frame_dec.width.into(),
-                    self.frame_dec.height.into(),
-                );
-            }
+    /// Reads an image descriptor into `frame_dec`, and returns the frame's color indices in the
+    /// order they're stored. The palette gets borrowed from `self` once the indices are used, so
+    /// any problems with them are recorded here.
+    fn read_frame_indices(&mut self) -> Result<Vec<u8>, DecodeError> {
+        self.read_image_descriptor()?;
+        let width = usize::from(self.frame_dec.width);
+        let height = usize::from(self.frame_dec.height);
+        let transparency_idx = self.frame_dec.transparency_idx;
+
+        let indices = self.read_image_data()?;
+        let palette_len = self
+            .frame_palette()
+            .map_or(ColorTable::null().table.len(), |pal| pal.table.len());
+        let out_of_bounds = indices
+            .iter()
+            .any(|&i| usize::from(i) >= palette_len && transparency_idx != Some(i.into()));
+        if out_of_bounds {
+            self.recover(DecodeError::ColorTableOutOfBounds)?;
+        }
+        if indices.len() < width * height {
+            self.recover(DecodeError::FrameUnderflow)?;
+        } else if indices.len() > width * height {
+            self.recover(DecodeError::FrameOverflow)?;
+        }
 
-            DisposalMethod::RestorePrevious => {}
+        Ok(indices)
+    }
+
+    /// The color table for the frame in `frame_dec`: its own, or else the global one.
+    fn frame_palette(&self) -> Option<&ColorTable> {
+        self.frame_dec
+            .palette
+            .as_ref()
+            .or(self.frame_global_palette())
+    }
+
+    /// Reads and decompresses a frame's image data into color indices. In recovery mode, whatever
+    /// was decoded before an error is returned.
+    fn read_image_data(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let min_code_size = self.rdr.read_u8()?;
+
+        let mut blocks = vec![];
+        if let Err(e) = read_blocks_into(&mut self.rdr, &mut blocks) {
+            self.recover(e)?;
         }
 
-        // Construct GifFrame and add it to the frame list
-        let frame = GifFrame::new(&self.frame_dec, new_canvas);
-        self.frames.push(frame);
+        let mut indices = vec![];
+        let sequences = match LZWIterator::new(blocks.into_iter().flatten(), min_code_size) {
+            Ok(iter) => iter,
+            Err(e) => {
+                self.recover(e.into())?;
+                return Ok(indices);
+            }
+        };
 
-        // Clear frame state
-        self.frame_dec = FrameDecoder::default();
+        for sequence_result in sequences {
+            match sequence_result {
+                Ok(sequence) => {
+                    let limits = &self.options.limits;
+                    limits.check(LimitKind::LzwOutput, indices.len() + sequence.len())?;
+                    indices.extend(sequence);
+                }
+                Err(e) => {
+                    self.recover(e.into())?;
+                    break;
+                }
+            }
+        }
 
-        Ok(())
+        Ok(indices)
     }
 
-    fn into_gif(self) -> DecodedGif {
+    fn into_gif(self, frames: Frames) -> DecodedGif {
+        let (canvas_width, canvas_height) = self.output_size();
         DecodedGif {
-            canvas_width: self.canvas_width,
-            canvas_height: self.canvas_height,
+            canvas_width,
+            canvas_height,
+            pixel_aspect_ratio: self.pixel_aspect_ratio,
             max_loops: self.max_loops,
-            num_frames: self.frames.len(),
+            num_frames: match &frames {
+                Frames::Stored(store) => store.len(),
+                Frames::Lazy(lazy) => lazy.len(),
+            },
             bg_color: self.bg_color.to_css_string(),
-            frames: self.frames,
+            global_palette: self.global_palette.as_ref().map(ColorTable::to_rgb),
+            frames,
+            pixel_format: self.options.pixel_format,
+            limits: self.options.limits,
+            comments: self.comments,
+            application_extensions: self.app_extensions,
+            warnings: self.warnings,
+            pinned: HashMap::new(),
+        }
+    }
+}
+
+/// A color profile found in the file, and the colors from before it was applied.
+struct ColorProfile {
+    transform: ColorTransform,
+    /// Frames before this one come before the profile in the file, and keep their colors
+    from_frame: usize,
+    original_palette: Option<ColorTable>,
+    original_bg_color: Color,
+}
+
+/// A block, as far as the decoder cares about it.
+enum Block<T = FrameDelta> {
+    /// An extension that doesn't make a frame, with its label
+    Extension(u8),
+    Image(T),
+    /// A Plain Text Extension that was drawn as a frame
+    Text(FrameDelta),
+    Trailer,
+    /// A byte that didn't start any known block, skipped in recovery mode
+    Skipped,
+}
+
+/// A frame's own image data, before it has been composited onto the canvas.
+struct FrameDelta {
+    left: u16,
+    top: u16,
+    width: u16,
+    height: u16,
+    delay: u16,
+    disposal_method: DisposalMethod,
+    transparency_idx: Option<usize>,
+    interlaced: bool,
+    user_input: bool,
+    palette: Option<ColorTable>,
+
+    image: Canvas,
+}
+
+/// Holds the working canvas that each frame is drawn on top of.
+#[derive(Clone)]
+struct Compositor {
+    canvas: Canvas,
+    policy: CompositingPolicy,
+    background: Color,
+    /// What to resample each frame to, if not the canvas size
+    output_size: Option<(usize, usize)>,
+    pixel_format: PixelFormat,
+    /// What transparent pixels become in formats without alpha
+    matte: Color,
+}
+
+impl Compositor {
+    fn new(width: usize, height: usize, policy: CompositingPolicy, background: Color) -> Self {
+        Self {
+            canvas: Canvas::from_bg_color(Color::transparent(), width, height),
+            policy,
+            background,
+            output_size: None,
+            pixel_format: PixelFormat::default(),
+            matte: Color::default(),
+        }
+    }
+
+    /// A compositor with the same settings, starting from a different working canvas.
+    fn with_canvas(&self, canvas: Canvas) -> Self {
+        Self { canvas, ..*self }
+    }
+
+    /// Whether disposing of `delta` with "restore to background" leaves transparency behind.
+    fn clears_to_transparent(&self, delta_transparency_idx: Option<usize>) -> bool {
+        self.policy
+            .dispose_color(self.background, delta_transparency_idx)
+            .is_transparent()
+    }
+
+    /// Returns the canvas to display for `delta`, then disposes of it.
+    fn composite(&mut self, delta: &FrameDelta) -> Canvas {
+        let shown = self
+            .canvas
+            .blit(&delta.image, delta.top.into(), delta.left.into());
+        self.advance(delta);
+        shown
+    }
+
+    /// Composites `delta` into a frame to hand out, resampling it if needed.
+    fn render(&mut self, delta: &FrameDelta) -> GifFrame {
+        let canvas = self.composite(delta);
+        let Some((width, height)) = self.output_size else {
+            return GifFrame::new(delta, canvas, self.pixel_format, self.matte);
+        };
+
+        let (canvas_width, canvas_height) = (canvas.width, canvas.height);
+        let canvas = canvas.resized(width, height);
+        let mut frame = GifFrame::new(delta, canvas, self.pixel_format, self.matte);
+
+        // Keep the frame's rectangle in the same place on the stretched canvas
+        let scale =
+            |pos: usize, from: usize, to: usize| u16::try_from(pos * to / from).unwrap_or(u16::MAX);
+        let right = usize::from(frame.left) + usize::from(frame.width);
+        let bottom = usize::from(frame.top) + usize::from(frame.height);
+        let right = scale(right, canvas_width, width);
+        let bottom = scale(bottom, canvas_height, height);
+        frame.left = scale(frame.left.into(), canvas_width, width);
+        frame.top = scale(frame.top.into(), canvas_height, height);
+        frame.width = right - frame.left;
+        frame.height = bottom - frame.top;
+        frame
+    }
+
+    /// Updates the working canvas as if `delta` had been displayed and then disposed of.
+    fn advance(&mut self, delta: &FrameDelta) {
+        match delta.disposal_method {
+            DisposalMethod::Keep => {
+                self.canvas
+                    .blit_mut(&delta.image, delta.top.into(), delta.left.into());
+            }
+
+            // Although the GIF specification says that this disposal method
+            // should clear the frame's area with the background color, every
+            // modern viewer/library I could find (except for PIL) clears it
+            // with transparency instead. Apparently it's de facto standard now.
+            // https://usage.imagemagick.org/anim_basics/#background
+            // The default policy follows them; see `CompositingPolicy`.
+            DisposalMethod::RestoreBackground => {
+                let color = self
+                    .policy
+                    .dispose_color(self.background, delta.transparency_idx);
+                self.canvas.fill_rect_mut(
+                    color,
+                    delta.top.into(),
+                    delta.left.into(),
+                    delta.width.into(),
+                    delta.height.into(),
+                );
+            }
+
+            DisposalMethod::RestorePrevious => {}
         }
     }
 }
@@ -326,16 +1326,19 @@ struct FrameDecoder {
     height: u16,
     delay: u16,
     disposal_method: DisposalMethod,
-
-    frame: Canvas,
+    user_input: bool,
 }
 
 impl FrameDecoder {
-    fn read_gfx_ctrl_ext<R: Read>(&mut self, mut rdr: R) -> Result<(), DecodeError> {
+    /// Returns a warning if something about the block had to be ignored.
+    fn read_gfx_ctrl_ext<R: Read>(
+        &mut self,
+        mut rdr: R,
+    ) -> Result<Option<WarningKind>, DecodeError> {
         let block = read_blocks(&mut rdr)?.concat();
         if block.len() < 4 {
             // Invalid block, skip it
-            return Ok(());
+            return Ok(Some(WarningKind::MalformedGraphicsControl));
         }
 
         let packed = block[0];
@@ -343,12 +1346,16 @@ impl FrameDecoder {
         let has_transparency = packed & 1 == 1;
 
         self.disposal_method = DisposalMethod::from_u8(disposal);
+        self.user_input = packed & 0b10 != 0;
         self.delay = LE::read_u16(&block[1..3]);
         if has_transparency {
             self.transparency_idx = Some(block[3].into());
         }
 
-        Ok(())
+        if disposal > 3 {
+            return Ok(Some(WarningKind::UnknownDisposalMethod(disposal)));
+        }
+        Ok(None)
     }
 
     fn read_image_descriptor<R: Read>(&mut self, mut rdr: R) -> Result<(), DecodeError> {
@@ -366,16 +1373,56 @@ impl FrameDecoder {
             self.palette = Some(ColorTable::read(&mut rdr, palette_size)?);
         }
 
-        self.frame =
-            Canvas::from_bg_color(Color::transparent(), self.width.into(), self.height.into());
-
         Ok(())
     }
 }
 
-fn read_blocks<R: Read>(mut rdr: R) -> Result<Vec<Vec<u8>>, DecodeError> {
+/// Keeps track of how many bytes have been read, so warnings can say where they came from.
+#[derive(Default)]
+struct PositionReader<R: Read> {
+    inner: R,
+    pos: u64,
+}
+
+impl<R: Read> PositionReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, pos: 0 }
+    }
+
+    fn position(&self) -> u64 {
+        self.pos
+    }
+}
+
+impl<T: AsRef<[u8]>> PositionReader<io::Cursor<T>> {
+    fn set_position(&mut self, pos: u64) {
+        self.inner.set_position(pos);
+        self.pos = pos;
+    }
+}
+
+impl<R: Read> Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// A transparent index as the byte it was read from.
+fn transparent_byte(transparency_idx: Option<usize>) -> Option<u8> {
+    // Read from a single byte, so this never truncates
+    transparency_idx.map(|i| i as u8)
+}
+
+fn read_blocks<R: Read>(rdr: R) -> Result<Vec<Vec<u8>>, DecodeError> {
     let mut blocks = vec![];
+    read_blocks_into(rdr, &mut blocks)?;
+    Ok(blocks)
+}
 
+/// Like [`read_blocks`], but keeps the blocks that were read before an error.
+fn read_blocks_into<R: Read>(mut rdr: R, blocks: &mut Vec<Vec<u8>>) -> Result<(), DecodeError> {
     loop {
         let block_size = rdr.read_u8()?;
         if block_size == 0 {
@@ -386,10 +1433,11 @@ fn read_blocks<R: Read>(mut rdr: R) -> Result<Vec<Vec<u8>>, DecodeError> {
         blocks.push(block);
     }
 
-    Ok(blocks)
+    Ok(())
 }
 
-#[derive(Default, Clone, Copy)]
+#[wasm_bindgen]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DisposalMethod {
     #[default]
     Keep,
@@ -423,42 +1471,32 @@ impl Canvas {
         }
     }
 
-    fn from_gif_data<R: Read>(
-        mut rdr: R,
+    /// Looks up each index in `palette`. Out-of-bounds indices are clamped to the end of the
+    /// table, and the data is padded with transparency or truncated to fit `width * height`.
+    fn from_indices(
+        indices: &[u8],
         palette: &ColorTable,
         transparency_index: Option<usize>,
         width: usize,
         height: usize,
-    ) -> Result<Self, DecodeError> {
-        let min_code_size = rdr.read_u8()?;
-        let blocks_iter = read_blocks(rdr)?.into_iter().flatten();
-
-        let mut data = vec![];
-
-        for sequence_result in LZWIterator::new(blocks_iter, min_code_size)? {
-            let sequence = sequence_result?;
-            for index in sequence {
+    ) -> Self {
+        let mut data: Vec<Color> = indices
+            .iter()
+            .map(|&index| {
                 let index: usize = index.into();
-                let color = match transparency_index {
+                match transparency_index {
                     Some(i) if i == index => Color::transparent(),
-                    _ => palette.get(index)?,
-                };
-                data.push(color);
-            }
-        }
-
-        let expected_size: usize = usize::from(width) * usize::from(height);
-        if data.len() < expected_size {
-            return Err(DecodeError::FrameUnderflow);
-        } else if data.len() > expected_size {
-            return Err(DecodeError::FrameOverflow);
-        }
+                    _ => palette.get_clamped(index),
+                }
+            })
+            .collect();
+        data.resize(width * height, Color::transparent());
 
-        Ok(Self {
+        Self {
             width,
             height,
             data,
-        })
+        }
     }
 
     fn blit(&self, src: &Canvas, top: usize, left: usize) -> Canvas {
@@ -479,9 +1517,31 @@ impl Canvas {
         }
     }
 
-    fn clear_rect_mut(&mut self, top: usize, left: usize, width: usize, height: usize) {
+    /// Nearest-neighbor resampling to a new size.
+    fn resized(&self, width: usize, height: usize) -> Canvas {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let row = y * self.height / height * self.width;
+            data.extend((0..width).map(|x| self.data[row + x * self.width / width]));
+        }
+
+        Canvas {
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn fill_rect_mut(
+        &mut self,
+        color: Color,
+        top: usize,
+        left: usize,
+        width: usize,
+        height: usize,
+    ) {
         for (_, _, dest_start_idx, dest_end_idx) in self.blit_iter(top, left, width, height) {
-            self.data[dest_start_idx..dest_end_idx].fill(Color::transparent());
+            self.data[dest_start_idx..dest_end_idx].fill(color);
         }
     }
 
@@ -492,9 +1552,10 @@ impl Canvas {
         width: usize,
         height: usize,
     ) -> impl Iterator<Item = (usize, usize, usize, usize)> {
-        let start_dest_col = left;
+        // Rectangles are clamped to the canvas, so one that's entirely outside it is empty
+        let start_dest_col = left.min(self.width);
         let end_dest_col = self.width.min(left.saturating_add(width));
-        let start_dest_row = top;
+        let start_dest_row = top.min(self.height);
         let end_dest_row = self.height.min(top.saturating_add(height));
 
         let src_width = width;
@@ -514,33 +1575,51 @@ impl Canvas {
     }
 
     fn deinterlaced(&self) -> Self {
-        let mut dest = vec![Color::default(); self.width * self.height];
-
-        let height8 = self.height.div_ceil(8);
-        let height4 = self.height.div_ceil(4);
-        let height2 = self.height.div_ceil(2);
-
-        for (dest_row_num, dest_row) in dest.chunks_exact_mut(self.width).enumerate() {
-            let source_row_num = if dest_row_num % 8 == 0 {
-                dest_row_num / 8
-            } else if dest_row_num % 4 == 0 {
-                height8 + (dest_row_num - 4) / 8
-            } else if dest_row_num % 2 == 0 {
-                height4 + (dest_row_num - 2) / 4
-            } else {
-                height2 + (dest_row_num / 2)
-            };
-
-            let i = source_row_num * self.width;
-            dest_row.copy_from_slice(&self.data[i..i + self.width]);
-        }
-
         Self {
             width: self.width,
             height: self.height,
-            data: dest,
+            data: deinterlace(&self.data, self.width, self.height),
+        }
+    }
+}
+
+/// Puts the rows of an interlaced image back in order. `data` must be `width * height` long.
+fn deinterlace<T: Copy + Default>(data: &[T], width: usize, height: usize) -> Vec<T> {
+    let mut dest = vec![T::default(); width * height];
+
+    let height8 = height.div_ceil(8);
+    let height4 = height.div_ceil(4);
+    let height2 = height.div_ceil(2);
+
+    for (dest_row_num, dest_row) in dest.chunks_exact_mut(width).enumerate() {
+        let source_row_num = if dest_row_num % 8 == 0 {
+            dest_row_num / 8
+        } else if dest_row_num % 4 == 0 {
+            height8 + (dest_row_num - 4) / 8
+        } else if dest_row_num % 2 == 0 {
+            height4 + (dest_row_num - 2) / 4
+        } else {
+            height2 + (dest_row_num / 2)
+        };
+
+        let i = source_row_num * width;
+        dest_row.copy_from_slice(&data[i..i + width]);
+    }
+
+    dest
+}
+
+/// Reorders rows for storing interlaced, the inverse of [`deinterlace`].
+fn interlace<T: Copy + Default>(data: &[T], width: usize, height: usize) -> Vec<T> {
+    let mut dest = Vec::with_capacity(width * height);
+
+    for (start, step) in [(0, 8), (4, 8), (2, 4), (1, 2)] {
+        for row in (start..height).step_by(step) {
+            dest.extend_from_slice(&data[row * width..(row + 1) * width]);
         }
     }
+
+    dest
 }
 
 #[derive(Clone, Copy)]
@@ -556,7 +1635,7 @@ impl Color {
     }
 
     fn is_transparent(&self) -> bool {
-        return !self.3;
+        !self.3
     }
 
     fn into_arr(self) -> [u8; 4] {
@@ -567,7 +1646,7 @@ impl Color {
         }
     }
 
-    fn to_css_string(&self) -> String {
+    fn to_css_string(self) -> String {
         format!("rgb({}, {}, {})", self.0, self.1, self.2)
     }
 }
@@ -578,6 +1657,7 @@ impl Default for Color {
     }
 }
 
+#[derive(Clone)]
 struct ColorTable {
     table: Vec<Color>,
 }
@@ -600,6 +1680,17 @@ impl ColorTable {
         Ok(Self { table })
     }
 
+    fn to_rgb(&self) -> Box<[u8]> {
+        self.table.iter().flat_map(|c| [c.0, c.1, c.2]).collect()
+    }
+
+    fn get_clamped(&self, index: usize) -> Color {
+        self.table
+            .get(index.min(self.table.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or_default()
+    }
+
     fn get(&self, index: usize) -> Result<Color, DecodeError> {
         self.table
             .get(index)
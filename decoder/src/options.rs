@@ -0,0 +1,219 @@
+use thiserror::Error;
+use wasm_bindgen::prelude::*;
+
+use crate::{Color, DecodeError};
+
+/// Settings that change how a GIF is decoded. The defaults match what browsers do.
+#[wasm_bindgen]
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DecodeOptions {
+    pub compositing: CompositingPolicy,
+
+    /// Keep going after errors in the file where possible, reporting them as warnings instead.
+    /// Truncated files keep every frame before the cut (and as much of the cut-off frame as
+    /// possible), frames with too little data are padded with transparency, unknown blocks are
+    /// skipped, and out-of-bounds color indices are clamped to the end of the color table.
+    pub recover: bool,
+
+    /// Draw Plain Text Extensions as frames of their own, with a built-in 8x8 font stretched to
+    /// the size of each character cell. Browsers skip them, so this is off by default.
+    pub plain_text: bool,
+
+    /// Convert colors to sRGB using the file's embedded ICC profile, if it has one. Only
+    /// matrix/TRC profiles are supported. Since the conversion is done on color tables rather
+    /// than pixels, it's cheap, but frames before the profile are left as-is.
+    pub color_management: bool,
+
+    /// Stretch frames so their pixels come out square, if the file says they aren't. Only the
+    /// shorter side of each pixel is stretched, so no detail is lost.
+    pub square_pixels: bool,
+
+    /// The layout of each frame's `image_data`.
+    pub pixel_format: PixelFormat,
+
+    /// The color that transparent pixels become in formats without alpha, as 0xRRGGBB.
+    /// Defaults to black.
+    pub matte_color: u32,
+
+    /// How much memory the file is allowed to make the decoder use.
+    pub limits: DecodeLimits,
+}
+
+#[wasm_bindgen]
+impl DecodeOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DecodeOptions {
+    pub(crate) fn matte(&self) -> Color {
+        let [_, r, g, b] = self.matte_color.to_be_bytes();
+        Color::rgb(r, g, b)
+    }
+}
+
+/// Caps on how big a file can make the decoder's output, so a hostile file fails with
+/// [`DecodeError::LimitExceeded`] instead of using up all the memory there is. Each one is
+/// checked before the memory it guards is allocated. `None` means no limit.
+///
+/// The defaults are far beyond any GIF meant to be looked at.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Pixels in the canvas, or in any one frame's rectangle. Defaults to 64M, or 8192x8192.
+    #[wasm_bindgen(js_name = maxCanvasPixels)]
+    pub max_canvas_pixels: Option<usize>,
+    /// Frames in the file, including plain text that's drawn as frames. Unlimited by default.
+    #[wasm_bindgen(js_name = maxFrames)]
+    pub max_frames: Option<usize>,
+    /// Bytes of decoded pixels kept in memory at once. That's one frame in the chosen pixel
+    /// format when streaming or decoding lazily, and every stored frame when decoding it all up
+    /// front, where it's checked before each frame is stored. Defaults to 1 GiB.
+    #[wasm_bindgen(js_name = maxOutputBytes)]
+    pub max_output_bytes: Option<usize>,
+    /// Color indices decompressed from any one frame. Anything past the frame's own pixels is
+    /// thrown away, but a small amount of data can decompress to a huge amount first. Defaults
+    /// to 64M.
+    #[wasm_bindgen(js_name = maxLzwOutput)]
+    pub max_lzw_output: Option<usize>,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_canvas_pixels: Some(1 << 26),
+            max_frames: None,
+            max_output_bytes: Some(1 << 30),
+            max_lzw_output: Some(1 << 26),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl DecodeLimits {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// No limits at all, for files that are known to be safe.
+    pub fn unlimited() -> Self {
+        Self {
+            max_canvas_pixels: None,
+            max_frames: None,
+            max_output_bytes: None,
+            max_lzw_output: None,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Fails if `value` is over the limit for `kind`.
+    pub(crate) fn check(&self, kind: LimitKind, value: usize) -> Result<(), DecodeError> {
+        let max = match kind {
+            LimitKind::CanvasPixels => self.max_canvas_pixels,
+            LimitKind::Frames => self.max_frames,
+            LimitKind::OutputBytes => self.max_output_bytes,
+            LimitKind::LzwOutput => self.max_lzw_output,
+        };
+        match max {
+            Some(max) if value > max => Err(DecodeError::LimitExceeded { kind, max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which of the [`DecodeLimits`] a file went over.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    #[error("pixels in the canvas or a frame")]
+    CanvasPixels,
+    #[error("frames")]
+    Frames,
+    #[error("bytes of output")]
+    OutputBytes,
+    #[error("decompressed color indices in a frame")]
+    LzwOutput,
+}
+
+/// What the "restore to background" disposal method fills the frame's area with.
+///
+/// The spec says to use the background color, but almost every modern viewer uses transparency
+/// instead. Pick whichever renderer the output should be compared against.
+#[wasm_bindgen]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompositingPolicy {
+    /// Fill with the global background color, or transparency if there's no global color table
+    SpecStrict,
+    /// Fill with transparency, like browsers and ImageMagick
+    #[default]
+    BrowserCompatible,
+    /// Fill with transparency if the frame has a transparent color, and with the background
+    /// color otherwise, like PIL/Pillow
+    PilCompatible,
+}
+
+impl CompositingPolicy {
+    /// The color to fill a disposed frame with. `background` should already be transparent if
+    /// the GIF has no global color table.
+    pub(crate) fn dispose_color(self, background: Color, transparency_idx: Option<usize>) -> Color {
+        match self {
+            Self::SpecStrict => background,
+            Self::BrowserCompatible => Color::transparent(),
+            Self::PilCompatible if transparency_idx.is_some() => Color::transparent(),
+            Self::PilCompatible => background,
+        }
+    }
+}
+
+/// How the pixels of each frame are laid out in memory.
+///
+/// GIF transparency is all-or-nothing, so premultiplying doesn't change any opaque pixels. It
+/// only guarantees that transparent pixels are all zeros.
+#[wasm_bindgen]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 4 bytes per pixel: red, green, blue, alpha
+    #[default]
+    Rgba8,
+    /// 4 bytes per pixel, with color multiplied by alpha
+    PremultipliedRgba8,
+    /// 4 bytes per pixel: blue, green, red, alpha, like most native surfaces
+    Bgra8,
+    /// 3 bytes per pixel, with transparency replaced by the matte color
+    Rgb8,
+    /// 1 byte per pixel of luminance, with transparency replaced by the matte color
+    Gray8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgba8 | Self::PremultipliedRgba8 | Self::Bgra8 => 4,
+            Self::Rgb8 => 3,
+            Self::Gray8 => 1,
+        }
+    }
+
+    pub(crate) fn write(self, color: Color, matte: Color, out: &mut Vec<u8>) {
+        let opaque = if color.is_transparent() { matte } else { color };
+        let Color(r, g, b, _) = opaque;
+
+        match self {
+            Self::Rgba8 | Self::PremultipliedRgba8 => out.extend(color.into_arr()),
+            Self::Bgra8 => {
+                let [r, g, b, a] = color.into_arr();
+                out.extend([b, g, r, a]);
+            }
+            Self::Rgb8 => out.extend([r, g, b]),
+            // Rec. 601 luma
+            Self::Gray8 => {
+                let luma =
+                    (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b) + 500) / 1000;
+                out.push(luma as u8);
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::deflate::{crc32, zlib_compress};
+use crate::encoder::EncodeError;
+
+pub(crate) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Appends a chunk with its length and checksum.
+pub(crate) fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    // Images are limited by u16 sides, so chunks stay far below 2^31 bytes
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+/// The color types this writes, all with 8 bits per channel.
+#[derive(Clone, Copy)]
+pub(crate) enum ColorType {
+    Gray = 0,
+    Rgb = 2,
+    Rgba = 6,
+}
+
+impl ColorType {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Gray => 1,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+/// PNGs have to be at least 1x1.
+pub(crate) fn check_size(width: usize, height: usize) -> Result<(), EncodeError> {
+    if width == 0 || height == 0 {
+        return Err(EncodeError::EmptyImage);
+    }
+    Ok(())
+}
+
+/// Writes a whole single image PNG.
+pub(crate) fn encode_png(
+    width: usize,
+    height: usize,
+    color_type: ColorType,
+    data: &[u8],
+) -> Result<Vec<u8>, EncodeError> {
+    check_size(width, height)?;
+    let mut out = SIGNATURE.to_vec();
+    // Sides come from GIFs, so they fit in a u16
+    write_chunk(
+        &mut out,
+        b"IHDR",
+        &ihdr(width as u32, height as u32, color_type),
+    );
+    write_chunk(&mut out, b"IDAT", &compress_image(width, color_type, data));
+    write_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+/// The header for 8-bit channels, without interlacing.
+pub(crate) fn ihdr(width: u32, height: u32, color_type: ColorType) -> Vec<u8> {
+    let mut data = Vec::with_capacity(13);
+    data.extend(width.to_be_bytes());
+    data.extend(height.to_be_bytes());
+    data.extend([8, color_type as u8, 0, 0, 0]);
+    data
+}
+
+/// Filters and compresses pixels into the contents of the image data chunks.
+pub(crate) fn compress_image(width: usize, color_type: ColorType, pixels: &[u8]) -> Vec<u8> {
+    let bpp = color_type.bytes_per_pixel();
+    let stride = width * bpp;
+    let mut filtered = Vec::with_capacity(pixels.len() + pixels.len() / stride.max(1));
+
+    let blank = vec![0; stride];
+    let mut prev: &[u8] = &blank;
+    let mut candidate = vec![0; stride];
+    let mut best = vec![0; stride];
+    for row in pixels.chunks_exact(stride.max(1)) {
+        // Pick the filter whose output is closest to zero, which usually compresses best
+        let mut best_filter = 0;
+        let mut best_score = u64::MAX;
+        for filter in 0..5 {
+            apply_filter(filter, bpp, row, prev, &mut candidate);
+            let score = candidate
+                .iter()
+                .map(|&b| u64::from((b as i8).unsigned_abs()))
+                .sum();
+            if score < best_score {
+                best_score = score;
+                best_filter = filter;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+        prev = row;
+    }
+
+    zlib_compress(&filtered)
+}
+
+fn apply_filter(filter: u8, bpp: usize, row: &[u8], prev: &[u8], out: &mut [u8]) {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up = prev[i];
+        let up_left = if i >= bpp { prev[i - bpp] } else { 0 };
+
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        out[i] = row[i].wrapping_sub(predicted);
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let pa = (p - i16::from(a)).abs();
+    let pb = (p - i16::from(b)).abs();
+    let pc = (p - i16::from(c)).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    ApplicationExtension, Block, Compositor, DecodeError, DecodeOptions, DecodeWarning, Decoder,
+    GifComment, GifFrame,
+};
+
+/// A decoder that is fed the file in chunks, rather than pulling bytes from a `Read`.
+///
+/// Bytes are buffered until a whole block (an extension or a frame) has arrived, at which point
+/// the block is decoded and the buffer is trimmed. Frames are returned from [`push`] as soon as
+/// they are complete.
+///
+/// [`push`]: PushDecoder::push
+#[derive(Default)]
+pub struct PushDecoder {
+    options: DecodeOptions,
+    /// Bytes received before the header and global color table were complete
+    pending: Vec<u8>,
+    decoder: Option<Decoder<io::Cursor<Vec<u8>>>>,
+    /// Created at the first frame, after any color profile has been read
+    compositor: Option<Compositor>,
+    scanner: BlockScanner,
+    finished: bool,
+}
+
+impl PushDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: &DecodeOptions) -> Self {
+        Self {
+            options: *options,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a chunk of the file, returning any frames that could be decoded because of it.
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<GifFrame>, DecodeError> {
+        if self.finished {
+            // Ignore anything after the trailer
+            return Ok(vec![]);
+        }
+
+        let decoder = match &mut self.decoder {
+            Some(decoder) => {
+                decoder.rdr.inner.get_mut().extend_from_slice(data);
+                decoder
+            }
+            None => {
+                self.pending.extend_from_slice(data);
+                if header_len(&self.pending).is_none() {
+                    return Ok(vec![]);
+                }
+                let buf = mem::take(&mut self.pending);
+                let decoder = Decoder::new(io::Cursor::new(buf), self.options)?;
+                self.decoder.insert(decoder)
+            }
+        };
+
+        let mut frames = vec![];
+        loop {
+            let pos = decoder.rdr.inner.position() as usize;
+            if self
+                .scanner
+                .scan(&decoder.rdr.inner.get_ref()[pos..])
+                .is_none()
+            {
+                break;
+            }
+            self.scanner = BlockScanner::default();
+
+            let Some(sigil) = decoder.read_sigil()? else {
+                break;
+            };
+            match decoder.read_block(sigil)? {
+                Block::Extension(_) | Block::Skipped => {}
+                Block::Image(delta) | Block::Text(delta) => {
+                    let compositor = self.compositor.get_or_insert_with(|| decoder.compositor());
+                    frames.push(compositor.render(&delta));
+                }
+                Block::Trailer => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+
+        // Drop everything that has already been decoded. The reader's own count keeps going, so
+        // warnings still have offsets into the whole file.
+        let pos = decoder.rdr.inner.position() as usize;
+        decoder.rdr.inner.get_mut().drain(..pos);
+        decoder.rdr.inner.set_position(0);
+
+        Ok(frames)
+    }
+
+    /// Signals that there is no more data. Fails if the file ended partway through a block,
+    /// unless recovery mode is on, in which case whatever is left of the frame is returned.
+    pub fn finish(&mut self) -> Result<Vec<GifFrame>, DecodeError> {
+        if self.finished {
+            return Ok(vec![]);
+        }
+        self.finished = true;
+
+        let Some(decoder) = &mut self.decoder else {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        };
+
+        // Anything left over is an incomplete block
+        let mut frames = vec![];
+        while let Some(delta) = decoder.next_frame()? {
+            let compositor = self.compositor.get_or_insert_with(|| decoder.compositor());
+            frames.push(compositor.render(&delta));
+        }
+        Ok(frames)
+    }
+
+    /// `None` until the logical screen descriptor has been received.
+    pub fn canvas_width(&self) -> Option<u16> {
+        self.decoder.as_ref().map(|d| d.output_size().0)
+    }
+
+    /// `None` until the logical screen descriptor has been received.
+    pub fn canvas_height(&self) -> Option<u16> {
+        self.decoder.as_ref().map(|d| d.output_size().1)
+    }
+
+    /// `None` until the logical screen descriptor has been received, or if the file doesn't
+    /// specify one.
+    pub fn pixel_aspect_ratio(&self) -> Option<f32> {
+        self.decoder.as_ref().and_then(|d| d.pixel_aspect_ratio)
+    }
+
+    pub fn max_loops(&self) -> Option<u16> {
+        self.decoder.as_ref().and_then(|d| d.max_loops)
+    }
+
+    pub fn bg_color(&self) -> Option<String> {
+        self.decoder.as_ref().map(|d| d.bg_color.to_css_string())
+    }
+
+    /// Comment extensions read so far.
+    pub fn comments(&self) -> &[GifComment] {
+        self.decoder.as_ref().map_or(&[], |d| &d.comments)
+    }
+
+    pub fn application_extensions(&self) -> &[ApplicationExtension] {
+        self.decoder.as_ref().map_or(&[], |d| &d.app_extensions)
+    }
+
+    /// Quirks that have been worked around so far.
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        self.decoder.as_ref().map_or(&[], |d| &d.warnings)
+    }
+}
+
+/// Length of the header, logical screen descriptor and global color table, if all of them are
+/// present in `buf`.
+fn header_len(buf: &[u8]) -> Option<usize> {
+    let packed = *buf.get(10)?;
+    let len = if packed & 0x80 != 0 {
+        13 + 3 * (1 << ((packed & 0x7) + 1))
+    } else {
+        13
+    };
+
+    (buf.len() >= len).then_some(len)
+}
+
+/// Checks whether the block at the front of a buffer has been received in full.
+///
+/// Progress is remembered between calls, so each byte of a large frame is only looked at once
+/// no matter how many chunks it arrives in.
+#[derive(Default)]
+struct BlockScanner {
+    /// Offset of the next sub-block length byte, once the fixed-size part has been scanned
+    pos: Option<usize>,
+}
+
+impl BlockScanner {
+    /// Returns the length of the block, or `None` if more data is needed.
+    fn scan(&mut self, buf: &[u8]) -> Option<usize> {
+        let mut pos = match self.pos {
+            Some(pos) => pos,
+            None => match *buf.first()? {
+                // Extension: sigil and label
+                0x21 if buf.len() >= 2 => 2,
+                // Image descriptor, local color table, then LZW minimum code size
+                0x2c if buf.len() >= 10 => {
+                    let packed = buf[9];
+                    if packed & 0x80 != 0 {
+                        11 + 3 * (1 << ((packed & 0x7) + 1))
+                    } else {
+                        11
+                    }
+                }
+                0x21 | 0x2c => return None,
+                // Trailer, or an unknown block that the decoder will reject
+                _ => return Some(1),
+            },
+        };
+
+        let result = loop {
+            match buf.get(pos) {
+                None => break None,
+                Some(0) => break Some(pos + 1),
+                Some(&n) => pos += 1 + usize::from(n),
+            }
+        };
+
+        self.pos = Some(pos);
+        result
+    }
+}
+
+/// JS wrapper around [`PushDecoder`], so `fetch()` response chunks can be fed in directly.
+#[wasm_bindgen(js_name = PushDecoder)]
+#[derive(Default)]
+pub struct PushDecoderJs {
+    inner: PushDecoder,
+    ready: VecDeque<GifFrame>,
+}
+
+#[wasm_bindgen(js_class = PushDecoder)]
+impl PushDecoderJs {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[wasm_bindgen(js_name = withOptions)]
+    pub fn with_options(options: &DecodeOptions) -> Self {
+        Self {
+            inner: PushDecoder::with_options(options),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the number of frames that are ready to be taken with `nextFrame()`.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<usize, JsError> {
+        self.ready.extend(self.inner.push(chunk)?);
+        Ok(self.ready.len())
+    }
+
+    /// Returns the number of frames that are ready to be taken with `nextFrame()`.
+    pub fn finish(&mut self) -> Result<usize, JsError> {
+        self.ready.extend(self.inner.finish()?);
+        Ok(self.ready.len())
+    }
+
+    #[wasm_bindgen(js_name = nextFrame)]
+    pub fn next_frame(&mut self) -> Option<GifFrame> {
+        self.ready.pop_front()
+    }
+
+    #[wasm_bindgen(getter, js_name = canvasWidth)]
+    pub fn canvas_width(&self) -> Option<u16> {
+        self.inner.canvas_width()
+    }
+
+    #[wasm_bindgen(getter, js_name = canvasHeight)]
+    pub fn canvas_height(&self) -> Option<u16> {
+        self.inner.canvas_height()
+    }
+
+    #[wasm_bindgen(getter, js_name = pixelAspectRatio)]
+    pub fn pixel_aspect_ratio(&self) -> Option<f32> {
+        self.inner.pixel_aspect_ratio()
+    }
+
+    #[wasm_bindgen(getter, js_name = maxLoops)]
+    pub fn max_loops(&self) -> Option<u16> {
+        self.inner.max_loops()
+    }
+
+    #[wasm_bindgen(getter, js_name = bgColor)]
+    pub fn bg_color(&self) -> Option<String> {
+        self.inner.bg_color()
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+/// An RGBA image reduced to a palette of at most 256 colors.
+pub(crate) struct Quantized {
+    /// RGB order, at most 256 entries including the transparent one
+    pub palette: Vec<[u8; 3]>,
+    pub indices: Vec<u8>,
+    pub transparent: Option<u8>,
+}
+
+/// Reduces RGBA pixels to a palette. Images with few enough colors keep them exactly, and the
+/// rest go through median cut. Pixels less than half opaque become fully transparent.
+pub(crate) fn quantize(rgba: &[u8]) -> Quantized {
+    let is_transparent = |px: &[u8]| px[3] < 128;
+
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    let mut has_transparency = false;
+    for px in rgba.chunks_exact(4) {
+        if is_transparent(px) {
+            has_transparency = true;
+        } else {
+            *histogram.entry([px[0], px[1], px[2]]).or_default() += 1;
+        }
+    }
+
+    let max_colors = if has_transparency { 255 } else { 256 };
+    let mut palette = if histogram.len() <= max_colors {
+        let mut colors: Vec<_> = histogram.keys().copied().collect();
+        colors.sort_unstable();
+        colors
+    } else {
+        median_cut(histogram.into_iter().collect(), max_colors)
+    };
+
+    // Exact colors map straight to their entry, and the rest are looked up once each
+    let mut lookup: HashMap<[u8; 3], u8> = palette
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| (c, i as u8))
+        .collect();
+
+    let transparent = has_transparency.then(|| {
+        palette.push([0, 0, 0]);
+        (palette.len() - 1) as u8
+    });
+
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|px| match transparent {
+            Some(t) if is_transparent(px) => t,
+            _ => {
+                let color = [px[0], px[1], px[2]];
+                let opaque = &palette[..palette.len() - usize::from(transparent.is_some())];
+                *lookup
+                    .entry(color)
+                    .or_insert_with(|| nearest(opaque, color))
+            }
+        })
+        .collect();
+
+    Quantized {
+        palette,
+        indices,
+        transparent,
+    }
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |c: &[u8; 3]| -> u32 {
+        c.iter()
+            .zip(color)
+            .map(|(&a, b)| u32::from(a.abs_diff(b)).pow(2))
+            .sum()
+    };
+
+    // The palette has at most 256 entries
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, c)| distance(c))
+        .map_or(0, |(i, _)| i as u8)
+}
+
+/// Splits the color space into `max_colors` boxes holding roughly equal numbers of pixels, and
+/// returns the average color of each.
+fn median_cut(colors: Vec<([u8; 3], u32)>, max_colors: usize) -> Vec<[u8; 3]> {
+    let mut boxes = vec![ColorBox { colors }];
+
+    while boxes.len() < max_colors {
+        let Some((i, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.colors.len() > 1)
+            .max_by_key(|(_, b)| b.widest_channel().1)
+        else {
+            break;
+        };
+
+        let (a, b) = boxes.swap_remove(i).split();
+        boxes.push(a);
+        boxes.push(b);
+    }
+
+    boxes.iter().map(ColorBox::average).collect()
+}
+
+struct ColorBox {
+    colors: Vec<([u8; 3], u32)>,
+}
+
+impl ColorBox {
+    /// The channel with the largest spread, and how large it is
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|ch| {
+                let (min, max) = self
+                    .colors
+                    .iter()
+                    .fold((u8::MAX, u8::MIN), |(min, max), (c, _)| {
+                        (min.min(c[ch]), max.max(c[ch]))
+                    });
+                (ch, max - min)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or_default()
+    }
+
+    /// Splits at the median pixel along the widest channel. Both halves get at least one color.
+    fn split(mut self) -> (Self, Self) {
+        let (ch, _) = self.widest_channel();
+        self.colors.sort_unstable_by_key(|(c, _)| c[ch]);
+
+        let total: u64 = self.colors.iter().map(|&(_, n)| u64::from(n)).sum();
+        let mut seen = 0;
+        let mut mid = self.colors.len() - 1;
+        for (i, &(_, n)) in self.colors.iter().enumerate() {
+            seen += u64::from(n);
+            if seen * 2 >= total {
+                mid = i + 1;
+                break;
+            }
+        }
+        let mid = mid.clamp(1, self.colors.len() - 1);
+
+        let rest = self.colors.split_off(mid);
+        (self, Self { colors: rest })
+    }
+
+    fn average(&self) -> [u8; 3] {
+        let mut sums = [0u64; 3];
+        let mut total = 0;
+        for &(c, n) in &self.colors {
+            for ch in 0..3 {
+                sums[ch] += u64::from(c[ch]) * u64::from(n);
+            }
+            total += u64::from(n);
+        }
+
+        // Boxes are never empty, and the average of bytes fits in a byte
+        sums.map(|sum| ((sum + total / 2) / total) as u8)
+    }
+}
@@ -0,0 +1,170 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::encoder::EncodeError;
+use crate::export::browser_delay;
+use crate::json::json_string;
+use crate::png::{check_size, encode_png, ColorType};
+use crate::{DecodedGif, LimitKind, PixelFormat};
+
+/// Every frame packed into a grid in one PNG, with an atlas of where each frame is.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct SpriteSheet {
+    #[wasm_bindgen(readonly, getter_with_clone)]
+    pub png: Box<[u8]>,
+    /// JSON in TexturePacker's array format, which most game engines and Aseprite read. Each
+    /// frame's delay is given as `duration`, in milliseconds.
+    #[wasm_bindgen(readonly, getter_with_clone)]
+    pub atlas: String,
+    #[wasm_bindgen(readonly)]
+    pub columns: usize,
+    #[wasm_bindgen(readonly)]
+    pub rows: usize,
+}
+
+#[wasm_bindgen]
+impl DecodedGif {
+    #[wasm_bindgen(js_name = framePng)]
+    pub fn frame_png_js(&self, i: usize) -> Result<Box<[u8]>, JsError> {
+        Ok(self.frame_png(i)?.into())
+    }
+
+    #[wasm_bindgen(js_name = spriteSheet)]
+    pub fn sprite_sheet_js(
+        &self,
+        columns: Option<usize>,
+        image_name: &str,
+    ) -> Result<SpriteSheet, JsError> {
+        Ok(self.sprite_sheet(columns, image_name)?)
+    }
+}
+
+impl DecodedGif {
+    /// Composites frame `i` into a PNG. Grayscale and RGB pixel formats give grayscale and RGB
+    /// PNGs, and the rest give RGBA.
+    pub fn frame_png(&self, i: usize) -> Result<Vec<u8>, EncodeError> {
+        let frame = self.get(i)?;
+        let (color_type, pixels) = png_pixels(self.pixel_format, &frame.image_data);
+        encode_png(
+            self.canvas_width.into(),
+            self.canvas_height.into(),
+            color_type,
+            &pixels,
+        )
+    }
+
+    /// Composites every frame into a PNG, in order.
+    pub fn frame_pngs(&self) -> impl Iterator<Item = Result<Vec<u8>, EncodeError>> + '_ {
+        let (width, height) = (self.canvas_width.into(), self.canvas_height.into());
+        self.frames().map(move |frame| {
+            let frame = frame?;
+            let (color_type, pixels) = png_pixels(self.pixel_format, &frame.image_data);
+            encode_png(width, height, color_type, &pixels)
+        })
+    }
+
+    /// Packs every frame into a grid, left to right and then top to bottom. With no number of
+    /// columns given, the grid is as close to square as it can be. `image_name` is what the
+    /// atlas calls the PNG. Sheets with more pixels than the GIF's [`DecodeLimits`] allow a
+    /// canvas fail with [`EncodeError::TooLarge`].
+    ///
+    /// [`DecodeLimits`]: crate::DecodeLimits
+    pub fn sprite_sheet(
+        &self,
+        columns: Option<usize>,
+        image_name: &str,
+    ) -> Result<SpriteSheet, EncodeError> {
+        let n = self.num_frames;
+        if n == 0 {
+            return Err(EncodeError::NoFrames);
+        }
+
+        let columns = columns
+            .unwrap_or_else(|| (n as f64).sqrt().ceil() as usize)
+            .clamp(1, n);
+        let rows = n.div_ceil(columns);
+
+        let bpp = self.pixel_format.bytes_per_pixel();
+        let (frame_width, frame_height) = (
+            usize::from(self.canvas_width),
+            usize::from(self.canvas_height),
+        );
+        check_size(frame_width, frame_height)?;
+        let (sheet_width, sheet_height) = (frame_width * columns, frame_height * rows);
+        // Held to the same size as a canvas the GIF could have, before anything is allocated
+        let too_large = || EncodeError::TooLarge {
+            width: sheet_width,
+            height: sheet_height,
+        };
+        let sheet_pixels = sheet_width
+            .checked_mul(sheet_height)
+            .ok_or_else(too_large)?;
+        self.limits
+            .check(LimitKind::CanvasPixels, sheet_pixels)
+            .map_err(|_| too_large())?;
+        let mut sheet = vec![0; sheet_pixels * bpp];
+
+        let size = format!("\"w\": {frame_width}, \"h\": {frame_height}");
+        let mut atlas = String::from("{\"frames\": [\n");
+        for (i, frame) in self.frames().enumerate() {
+            let frame = frame?;
+            let (x, y) = (i % columns * frame_width, i / columns * frame_height);
+
+            for (row, src) in frame.image_data.chunks_exact(frame_width * bpp).enumerate() {
+                let start = ((y + row) * sheet_width + x) * bpp;
+                sheet[start..start + src.len()].copy_from_slice(src);
+            }
+
+            let separator = if i + 1 < n { "," } else { "" };
+            let duration = u32::from(browser_delay(frame.delay)) * 10;
+            // Writing to a String can't fail
+            let _ = writeln!(
+                atlas,
+                "  {{\"filename\": \"frame_{i}\", \"frame\": {{\"x\": {x}, \"y\": {y}, {size}}}, \
+                 \"rotated\": false, \"trimmed\": false, \
+                 \"spriteSourceSize\": {{\"x\": 0, \"y\": 0, {size}}}, \
+                 \"sourceSize\": {{{size}}}, \"duration\": {duration}}}{separator}",
+            );
+        }
+
+        let (color_type, pixels) = png_pixels(self.pixel_format, &sheet);
+        let format = match color_type {
+            ColorType::Gray => "L8",
+            ColorType::Rgb => "RGB888",
+            ColorType::Rgba => "RGBA8888",
+        };
+        let _ = write!(
+            atlas,
+            "],\n\"meta\": {{\"image\": {}, \"format\": \"{format}\", \
+             \"size\": {{\"w\": {sheet_width}, \"h\": {sheet_height}}}, \"scale\": \"1\"}}}}\n",
+            json_string(image_name),
+        );
+
+        Ok(SpriteSheet {
+            png: encode_png(sheet_width, sheet_height, color_type, &pixels)?.into(),
+            atlas,
+            columns,
+            rows,
+        })
+    }
+}
+
+/// How pixels in `format` are stored in a PNG, reordering them if PNG has no matching layout.
+fn png_pixels(format: PixelFormat, data: &[u8]) -> (ColorType, Cow<'_, [u8]>) {
+    match format {
+        // GIF alpha is all-or-nothing, so premultiplied pixels are the same as straight ones
+        PixelFormat::Rgba8 | PixelFormat::PremultipliedRgba8 => (ColorType::Rgba, data.into()),
+        PixelFormat::Bgra8 => {
+            let rgba = data
+                .chunks_exact(4)
+                .flat_map(|px| [px[2], px[1], px[0], px[3]])
+                .collect();
+            (ColorType::Rgba, Cow::Owned(rgba))
+        }
+        PixelFormat::Rgb8 => (ColorType::Rgb, data.into()),
+        PixelFormat::Gray8 => (ColorType::Gray, data.into()),
+    }
+}
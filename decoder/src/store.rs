@@ -0,0 +1,99 @@
+use std::mem;
+
+use crate::{Canvas, Color, Compositor, FrameDelta, GifFrame};
+
+/// How many frames apart the stored keyframes are. Compositing any frame takes at most this many
+/// steps from the nearest keyframe.
+const KEYFRAME_INTERVAL: usize = 16;
+
+/// Stores each frame as the rectangle it changes, rather than as a full canvas.
+///
+/// Every [`KEYFRAME_INTERVAL`]th frame, a copy of the working canvas from just before that frame
+/// is saved, so compositing a frame never has to start from the beginning of the GIF.
+pub(crate) struct FrameStore {
+    width: usize,
+    height: usize,
+    deltas: Vec<FrameDelta>,
+    keyframes: Vec<Canvas>,
+    // Size of the pixels in `deltas` and `keyframes`, in bytes
+    bytes: usize,
+
+    // Working canvas after the last pushed frame
+    compositor: Compositor,
+}
+
+impl FrameStore {
+    /// `compositor` should have an empty working canvas.
+    pub fn new(compositor: Compositor) -> Self {
+        Self {
+            width: compositor.canvas.width,
+            height: compositor.canvas.height,
+            deltas: vec![],
+            keyframes: vec![],
+            bytes: 0,
+            compositor,
+        }
+    }
+
+    pub fn push(&mut self, delta: FrameDelta) {
+        self.bytes = self.bytes_with(&delta);
+        if self.needs_keyframe() {
+            self.keyframes.push(self.compositor.canvas.clone());
+        }
+
+        self.compositor.advance(&delta);
+        self.deltas.push(delta);
+    }
+
+    /// Whether a keyframe is saved before the next frame pushed.
+    fn needs_keyframe(&self) -> bool {
+        self.keyframes.len() * KEYFRAME_INTERVAL == self.deltas.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// How much memory the stored pixels would take up with `delta` pushed, including any
+    /// keyframe saved for it.
+    pub fn bytes_with(&self, delta: &FrameDelta) -> usize {
+        let keyframe = if self.needs_keyframe() {
+            self.width * self.height
+        } else {
+            0
+        };
+        self.bytes + (keyframe + delta.image.data.len()) * mem::size_of::<Color>()
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn delta(&self, i: usize) -> Option<&FrameDelta> {
+        self.deltas.get(i)
+    }
+
+    pub fn delay(&self, i: usize) -> Option<u16> {
+        self.deltas.get(i).map(|delta| delta.delay)
+    }
+
+    pub fn get(&self, i: usize) -> Option<GifFrame> {
+        let delta = self.deltas.get(i)?;
+
+        let k = i / KEYFRAME_INTERVAL;
+        let mut compositor = self.compositor.with_canvas(self.keyframes[k].clone());
+        for delta in &self.deltas[k * KEYFRAME_INTERVAL..i] {
+            compositor.advance(delta);
+        }
+
+        Some(compositor.render(delta))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = GifFrame> + '_ {
+        let blank = Canvas::from_bg_color(Color::transparent(), self.width, self.height);
+        let mut compositor = self.compositor.with_canvas(blank);
+        self.deltas
+            .iter()
+            .map(move |delta| compositor.render(delta))
+    }
+}
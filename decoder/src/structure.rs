@@ -0,0 +1,296 @@
+use std::io::{self, Read};
+use std::mem;
+
+use byteorder::ReadBytesExt;
+
+use crate::{
+    read_blocks, transparent_byte, Block, DecodeError, DecodeLimits, DecodeOptions, DecodeWarning,
+    Decoder, DisposalMethod, FrameDecoder, WarningKind,
+};
+
+/// Every block in a GIF file, in the order the decoder reads them.
+#[derive(Debug)]
+pub struct GifStructure {
+    pub blocks: Vec<GifBlock>,
+    /// Offset just past the last block that was read. The decoder ignores anything after the
+    /// trailer, and can't read past a block that's cut short or malformed.
+    pub end: u64,
+    /// Why reading stopped before the end of the file, if it did
+    pub error: Option<DecodeError>,
+    /// What the decoder would warn about. Problems with the image data itself aren't included,
+    /// since it isn't decompressed.
+    pub warnings: Vec<DecodeWarning>,
+}
+
+/// A block, where it is in the file, and the blocks inside it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GifBlock {
+    /// Byte offset of the block's first byte
+    pub offset: u64,
+    /// Length in bytes, including any blocks inside it and the terminator after its sub-blocks
+    pub len: u64,
+    pub kind: BlockKind,
+    /// Length of each data sub-block, for extensions and image data
+    pub sub_blocks: Vec<u8>,
+    /// A logical screen descriptor's global color table, or an image's local color table and
+    /// image data
+    pub children: Vec<GifBlock>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockKind {
+    /// "GIF" and the version, "87a" or "89a"
+    Header {
+        version: String,
+    },
+    LogicalScreenDescriptor {
+        width: u16,
+        height: u16,
+        background_index: u8,
+        /// The raw byte, where 0 means no ratio is given
+        aspect_ratio: u8,
+    },
+    ColorTable {
+        colors: usize,
+    },
+    /// As the decoder reads it. Blocks too short to read show up as an [`Extension`].
+    ///
+    /// [`Extension`]: BlockKind::Extension
+    GraphicsControl {
+        disposal_method: DisposalMethod,
+        user_input: bool,
+        delay: u16,
+        transparent: Option<u8>,
+    },
+    Comment,
+    PlainText,
+    /// With the application identifier and auth code, like "NETSCAPE2.0"
+    Application {
+        identifier: String,
+    },
+    /// Any other extension, with its label
+    Extension {
+        label: u8,
+    },
+    /// An image descriptor
+    Image {
+        left: u16,
+        top: u16,
+        width: u16,
+        height: u16,
+        interlaced: bool,
+    },
+    /// LZW-compressed color indices, as `sub_blocks`
+    ImageData {
+        min_code_size: u8,
+    },
+    Trailer,
+    /// A run of bytes that don't start any known block, which recovery mode skips over
+    Unknown,
+}
+
+impl GifBlock {
+    fn new(offset: u64, len: u64, kind: BlockKind) -> Self {
+        Self {
+            offset,
+            len,
+            kind,
+            sub_blocks: vec![],
+            children: vec![],
+        }
+    }
+}
+
+/// Walks the file block by block, the same way the decoder does, without decompressing any
+/// image data. Meant for debugging files that don't decode the way they should.
+///
+/// Unknown bytes are skipped over like in recovery mode. Anything else that would stop the
+/// decoder stops the walk too, but the blocks before it are still returned.
+pub fn read_structure(data: &[u8]) -> GifStructure {
+    // Nothing the size of the canvas or a frame is allocated, so there's nothing to limit
+    let options = DecodeOptions {
+        recover: true,
+        limits: DecodeLimits::unlimited(),
+        ..Default::default()
+    };
+    let mut decoder = match Decoder::new(io::Cursor::new(data), options) {
+        Ok(decoder) => decoder,
+        Err(e) => {
+            return GifStructure {
+                blocks: vec![],
+                end: 0,
+                error: Some(e),
+                warnings: vec![],
+            }
+        }
+    };
+
+    // The decoder has already checked that these are all there
+    let mut blocks = vec![GifBlock::new(
+        0,
+        6,
+        BlockKind::Header {
+            version: String::from_utf8_lossy(&data[3..6]).into_owned(),
+        },
+    )];
+    let mut screen = GifBlock::new(
+        6,
+        7,
+        BlockKind::LogicalScreenDescriptor {
+            width: decoder.canvas_width,
+            height: decoder.canvas_height,
+            background_index: data[11],
+            aspect_ratio: data[12],
+        },
+    );
+    if let Some(palette) = &decoder.global_palette {
+        let colors = palette.table.len();
+        screen.children.push(GifBlock::new(
+            13,
+            3 * colors as u64,
+            BlockKind::ColorTable { colors },
+        ));
+        screen.len += 3 * colors as u64;
+    }
+    blocks.push(screen);
+
+    let error = decoder.read_structure(data, &mut blocks).err();
+    let end = blocks.last().map_or(0, |block| block.offset + block.len);
+    GifStructure {
+        blocks,
+        end,
+        error,
+        warnings: decoder.warnings,
+    }
+}
+
+impl<R: Read> Decoder<R> {
+    /// Reads blocks after the global color table until the trailer, adding each one to `blocks`.
+    /// `data` is the whole file, which the sub-block lengths are read back from.
+    fn read_structure(
+        &mut self,
+        data: &[u8],
+        blocks: &mut Vec<GifBlock>,
+    ) -> Result<(), DecodeError> {
+        while let Some(sigil) = self.read_sigil()? {
+            let offset = self.block_offset;
+            // So settings from an earlier graphics control block don't carry over into this one
+            self.frame_dec = FrameDecoder::default();
+            let warnings = self.warnings.len();
+
+            let block = match self.read_block_with(sigil, |dec| dec.read_image_structure(data))? {
+                Block::Image(image) => image,
+                Block::Extension(label) => self.extension_structure(data, label, warnings),
+                // Only drawn with the plain text option, which is off
+                Block::Text(_) => self.extension_structure(data, 0x01, warnings),
+                Block::Trailer => {
+                    blocks.push(GifBlock::new(offset, 1, BlockKind::Trailer));
+                    break;
+                }
+                Block::Skipped => {
+                    match blocks.last_mut() {
+                        Some(last) if last.kind == BlockKind::Unknown => last.len += 1,
+                        _ => blocks.push(GifBlock::new(offset, 1, BlockKind::Unknown)),
+                    }
+                    continue;
+                }
+            };
+            blocks.push(block);
+        }
+
+        Ok(())
+    }
+
+    /// Describes the extension the decoder just read, which started at `block_offset`.
+    /// `warnings` is how many warnings there were before it.
+    fn extension_structure(&self, data: &[u8], label: u8, warnings: usize) -> GifBlock {
+        let offset = self.block_offset;
+        let sub_blocks = sub_block_lengths(data, offset + 2);
+
+        let kind = match label {
+            0xf9 => {
+                let malformed = self.warnings[warnings..]
+                    .iter()
+                    .any(|w| w.kind == WarningKind::MalformedGraphicsControl);
+                let fdec = &self.frame_dec;
+                if malformed {
+                    BlockKind::Extension { label }
+                } else {
+                    BlockKind::GraphicsControl {
+                        disposal_method: fdec.disposal_method,
+                        user_input: fdec.user_input,
+                        delay: fdec.delay,
+                        transparent: transparent_byte(fdec.transparency_idx),
+                    }
+                }
+            }
+            0xfe => BlockKind::Comment,
+            0x01 => BlockKind::PlainText,
+            0xff if sub_blocks.first() == Some(&11) => {
+                let start = offset as usize + 3;
+                BlockKind::Application {
+                    identifier: String::from_utf8_lossy(&data[start..start + 11]).into_owned(),
+                }
+            }
+            _ => BlockKind::Extension { label },
+        };
+
+        let mut block = GifBlock::new(offset, self.rdr.position() - offset, kind);
+        block.sub_blocks = sub_blocks;
+        block
+    }
+
+    /// Reads an image descriptor and skips over its image data.
+    fn read_image_structure(&mut self, data: &[u8]) -> Result<GifBlock, DecodeError> {
+        let offset = self.block_offset;
+        self.read_image_descriptor()?;
+        let fdec = mem::take(&mut self.frame_dec);
+        let mut image = GifBlock::new(
+            offset,
+            0,
+            BlockKind::Image {
+                left: fdec.left,
+                top: fdec.top,
+                width: fdec.width,
+                height: fdec.height,
+                interlaced: fdec.interlaced,
+            },
+        );
+        if let Some(palette) = &fdec.palette {
+            let colors = palette.table.len();
+            image.children.push(GifBlock::new(
+                offset + 10,
+                3 * colors as u64,
+                BlockKind::ColorTable { colors },
+            ));
+        }
+
+        let data_offset = self.rdr.position();
+        let min_code_size = self.rdr.read_u8()?;
+        read_blocks(&mut self.rdr)?;
+        let mut image_data = GifBlock::new(
+            data_offset,
+            self.rdr.position() - data_offset,
+            BlockKind::ImageData { min_code_size },
+        );
+        image_data.sub_blocks = sub_block_lengths(data, data_offset + 1);
+        image.children.push(image_data);
+
+        image.len = self.rdr.position() - offset;
+        self.frame_index += 1;
+        Ok(image)
+    }
+}
+
+/// The lengths of the sub-blocks starting at `offset`, which the decoder has already read up to
+/// their terminator.
+fn sub_block_lengths(data: &[u8], offset: u64) -> Vec<u8> {
+    let mut lengths = vec![];
+    // Offsets within data that's already in memory
+    let mut pos = offset as usize;
+    while data[pos] != 0 {
+        lengths.push(data[pos]);
+        pos += usize::from(data[pos]) + 1;
+    }
+    lengths
+}
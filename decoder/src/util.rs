@@ -1,10 +1,12 @@
+use std::collections::HashMap;
+
 use thiserror::Error;
 
 const MIN_CODE_SIZE: u8 = 2;
 const MAX_CODE_SIZE: u8 = 12;
 const MAX_CODE_TABLE_SIZE: usize = 1 << MAX_CODE_SIZE;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum LZWError {
     #[error(
         "Code size {0} is outside the allowed range of [{min}, 8]",
@@ -38,7 +40,7 @@ impl<Inner: Iterator<Item = u8>> LZWIterator<Inner> {
     where
         IntoIter: IntoIterator<Item = u8, IntoIter = Inner>,
     {
-        if min_code_size < MIN_CODE_SIZE || min_code_size > 8 {
+        if !(MIN_CODE_SIZE..=8).contains(&min_code_size) {
             return Err(LZWError::CodeSizeOutOfRange(min_code_size));
         }
 
@@ -98,7 +100,7 @@ impl<Inner: Iterator<Item = u8>> LZWIterator<Inner> {
                 let new_sequence = {
                     let src = &self.code_table[prev_code];
                     let mut v = vec![0; src.len() + 1];
-                    v[0..(src.len())].copy_from_slice(&src);
+                    v[0..(src.len())].copy_from_slice(src);
                     v[src.len()] = next_symbol;
                     v
                 };
@@ -149,6 +151,69 @@ impl<Inner: Iterator<Item = u8>> Iterator for LZWIterator<Inner> {
     }
 }
 
+/// Compresses `indices` into a GIF LZW bitstream, the inverse of [`LZWIterator`]. Every index
+/// must be below `1 << min_code_size`. The output isn't split into sub-blocks.
+pub fn lzw_compress(indices: &[u8], min_code_size: u8) -> Result<Vec<u8>, LZWError> {
+    if !(MIN_CODE_SIZE..=8).contains(&min_code_size) {
+        return Err(LZWError::CodeSizeOutOfRange(min_code_size));
+    }
+
+    let clear_code = 1 << min_code_size;
+    let end_code = clear_code + 1;
+    if let Some(&i) = indices.iter().find(|&&i| usize::from(i) >= clear_code) {
+        return Err(LZWError::CodeOutOfBounds(i.into(), clear_code - 1));
+    }
+
+    let mut bits = BitWriter::default();
+    let mut cur_code_size = min_code_size + 1;
+    // Maps a code and the symbol following it to the code for both
+    let mut code_table: HashMap<(usize, u8), usize> = HashMap::new();
+    let mut next_code = end_code + 1;
+
+    bits.put(clear_code, cur_code_size);
+    let Some((&first, rest)) = indices.split_first() else {
+        bits.put(end_code, cur_code_size);
+        return Ok(bits.finish());
+    };
+
+    let mut prefix = usize::from(first);
+    for &symbol in rest {
+        if let Some(&code) = code_table.get(&(prefix, symbol)) {
+            prefix = code;
+            continue;
+        }
+
+        bits.put(prefix, cur_code_size);
+
+        if next_code == MAX_CODE_TABLE_SIZE {
+            // The decoder's table is full too, so start over
+            bits.put(clear_code, cur_code_size);
+            code_table.clear();
+            cur_code_size = min_code_size + 1;
+            next_code = end_code + 1;
+        } else {
+            code_table.insert((prefix, symbol), next_code);
+            // The decoder adds each code one step behind us, so it widens once it has seen the
+            // code we just assigned
+            if next_code == 1 << cur_code_size && cur_code_size < MAX_CODE_SIZE {
+                cur_code_size += 1;
+            }
+            next_code += 1;
+        }
+
+        prefix = symbol.into();
+    }
+
+    bits.put(prefix, cur_code_size);
+    // The decoder adds one last code after reading the final prefix, which may widen the end code
+    if next_code == 1 << cur_code_size && cur_code_size < MAX_CODE_SIZE {
+        cur_code_size += 1;
+    }
+    bits.put(end_code, cur_code_size);
+
+    Ok(bits.finish())
+}
+
 struct BitCursor<I: Iterator<Item = u8>> {
     iterator: I,
     cur: usize,
@@ -191,3 +256,33 @@ impl<I: Iterator<Item = u8>> BitCursor<I> {
         Some(output)
     }
 }
+
+/// Packs codes least significant bit first, the way [`BitCursor`] reads them. Deflate uses the
+/// same order.
+#[derive(Default)]
+pub(crate) struct BitWriter {
+    bytes: Vec<u8>,
+    cur: u32,
+    counter: u8,
+}
+
+impl BitWriter {
+    /// Writes the low `n` bits of `code`, where `n` is at most 16.
+    pub fn put(&mut self, code: usize, n: u8) {
+        // Fewer than 8 bits are ever left over, so up to 16 more fit
+        self.cur |= ((code & ((1 << n) - 1)) as u32) << self.counter;
+        self.counter += n;
+        while self.counter >= 8 {
+            self.bytes.push(self.cur as u8);
+            self.cur >>= 8;
+            self.counter -= 8;
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.counter > 0 {
+            self.bytes.push(self.cur as u8);
+        }
+        self.bytes
+    }
+}
@@ -0,0 +1,51 @@
+use thiserror::Error;
+
+use crate::util::LZWError;
+
+/// Something odd about the file that the decoder worked around.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{kind} (frame {frame}, offset {offset})")]
+pub struct DecodeWarning {
+    /// Byte offset of the block the problem was found in
+    pub offset: u64,
+    /// Index of the frame the block belongs to, or comes before
+    pub frame: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    // Only reported in recovery mode, in place of the equivalent `DecodeError`
+    #[error("File ended partway through a block")]
+    Truncated,
+    #[error("Skipped unknown block type 0x{0:x}")]
+    UnknownBlock(u8),
+    #[error("Color table indexed out of bounds")]
+    ColorTableOutOfBounds,
+    #[error("Frame data underflow")]
+    FrameUnderflow,
+    #[error("Frame data overflow")]
+    FrameOverflow,
+    #[error("LZW decompression error: {0}")]
+    LZWError(LZWError),
+
+    // Always reported
+    #[error("File has no trailer")]
+    MissingTrailer,
+    #[error("Background color index {0} is outside the global color table")]
+    BackgroundColorOutOfBounds(u8),
+    #[error("Graphics control extension is too short, ignored it")]
+    MalformedGraphicsControl,
+    #[error("Unknown disposal method {0}, treated as \"keep\"")]
+    UnknownDisposalMethod(u8),
+    #[error("Looping extension is missing a sub-block or has one that couldn't be read")]
+    MalformedNetscapeExtension,
+    #[error("Plain text extension is too short, ignored it")]
+    MalformedPlainText,
+    #[error("Color profile isn't an RGB matrix/TRC profile, ignored it")]
+    UnsupportedColorProfile,
+    #[error("Frame has no color table, drew it in black")]
+    MissingColorTable,
+    #[error("Frame extends past the edge of the canvas")]
+    FrameOutsideCanvas,
+}
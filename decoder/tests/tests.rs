@@ -49,6 +49,18 @@ mod valid_gifs {
         compare_frames(&decoded, &expected);
     }
 
+    /// Frames composited from a keyframe should match the ones composited in order
+    #[test]
+    pub fn random_access() {
+        let decoded = read_gif_file(test_input("1bpp.gif")).unwrap();
+        let sequential: Vec<_> = decoded.frames().map(Result::unwrap).collect();
+
+        for i in [60, 0, 17, 16, 15, 33] {
+            assert_eq!(decoded.get(i).unwrap().image_data, sequential[i].image_data);
+        }
+        assert!(decoded.get(61).is_err());
+    }
+
     /// Disposal method 1: keep
     #[test]
     pub fn disposal_method_1() {
@@ -74,13 +86,423 @@ mod valid_gifs {
     }
 }
 
+mod frame_metadata {
+    use gif_controls_decoder::{
+        decode, decode_lazy_with_options, decode_with_options, DecodeOptions, DisposalMethod,
+        FrameWait,
+    };
+
+    use crate::util::*;
+
+    #[test]
+    pub fn disposal_and_transparency() {
+        let decoded = read_gif_file(test_input("dispose3.gif")).unwrap();
+
+        let first = decoded.get(0).unwrap();
+        assert_eq!(first.disposal_method, DisposalMethod::Keep);
+        assert_eq!(first.transparency_index, None);
+        assert!(first.local_palette.is_none());
+
+        let second = decoded.get(1).unwrap();
+        assert_eq!(second.disposal_method, DisposalMethod::RestorePrevious);
+        assert_eq!(second.transparency_index, Some(29));
+        assert!(!second.interlaced);
+        assert_eq!(second.local_palette.unwrap().len() % 3, 0);
+    }
+
+    #[test]
+    pub fn interlace_flag() {
+        let decoded = read_gif_file(test_input("interlaced.gif")).unwrap();
+        assert!(decoded.get(0).unwrap().interlaced);
+    }
+
+    #[test]
+    pub fn user_input() {
+        let wait_for = |packed: u8, delay: u8| {
+            let data = tiny_gif(&[0x21, 0xf9, 4, packed, delay, 0, 0, 0], true);
+            let decoded = decode_lazy_with_options(data.into(), &DecodeOptions::default());
+            decoded.unwrap().get(0).unwrap().wait()
+        };
+
+        assert_eq!(wait_for(0b00, 10), FrameWait::Delay(10));
+        assert_eq!(wait_for(0b10, 0), FrameWait::UserInput { timeout: None });
+        assert_eq!(
+            wait_for(0b10, 50),
+            FrameWait::UserInput { timeout: Some(50) }
+        );
+
+        let decoded = read_gif_file(test_input("dispose1.gif")).unwrap();
+        assert!(decoded.frames().all(|frame| !frame.unwrap().user_input));
+    }
+
+    #[test]
+    pub fn pixel_aspect_ratio() {
+        let square = DecodeOptions {
+            square_pixels: true,
+            ..Default::default()
+        };
+        let with_aspect = |aspect: u8| {
+            let mut data = tiny_gif(&[], true);
+            data[12] = aspect;
+            data.into_boxed_slice()
+        };
+
+        let decoded = decode_with_options(with_aspect(0), &square).unwrap();
+        assert_eq!(decoded.pixel_aspect_ratio, None);
+        assert_eq!((decoded.canvas_width, decoded.canvas_height), (1, 1));
+
+        // Wide pixels are stretched horizontally, but only with the option
+        let decoded = decode(with_aspect(113)).unwrap();
+        assert_eq!(decoded.pixel_aspect_ratio, Some(2.0));
+        assert_eq!((decoded.canvas_width, decoded.canvas_height), (1, 1));
+
+        let decoded = decode_with_options(with_aspect(113), &square).unwrap();
+        assert_eq!((decoded.canvas_width, decoded.canvas_height), (2, 1));
+        let frame = decoded.get(0).unwrap();
+        assert_eq!((frame.width, frame.height), (2, 1));
+        assert_eq!(frame.image_data.len(), 2 * 4);
+
+        // Tall pixels are stretched vertically
+        let decoded = decode_lazy_with_options(with_aspect(17), &square).unwrap();
+        assert_eq!(decoded.pixel_aspect_ratio, Some(0.5));
+        assert_eq!((decoded.canvas_width, decoded.canvas_height), (1, 2));
+        let frame = decoded.get(0).unwrap();
+        assert_eq!((frame.width, frame.height), (1, 2));
+        assert_eq!(frame.image_data.len(), 2 * 4);
+    }
+}
+
+mod compositing {
+    use gif_controls_decoder::{
+        decode, decode_lazy_with_options, decode_with_options, CompositingPolicy, DecodeOptions,
+        PixelFormat,
+    };
+
+    use crate::util::*;
+
+    /// RGBA of frame 2 at (6, 100), which frame 1 covers and then disposes, but frame 2 doesn't
+    fn disposed_pixel(policy: CompositingPolicy, lazy: bool) -> Vec<u8> {
+        let options = DecodeOptions {
+            compositing: policy,
+            ..Default::default()
+        };
+        let path = test_input("dispose2.gif");
+        let decoded = if lazy {
+            let data = std::fs::read(path).unwrap().into_boxed_slice();
+            decode_lazy_with_options(data, &options).unwrap()
+        } else {
+            read_gif_file_with_options(path, &options).unwrap()
+        };
+
+        let i = (100 * usize::from(decoded.canvas_width) + 6) * 4;
+        decoded.get(2).unwrap().image_data[i..i + 4].to_vec()
+    }
+
+    #[test]
+    pub fn restore_background_policies() {
+        // Global color table entry 0
+        let data = std::fs::read(test_input("dispose2.gif")).unwrap();
+        let bg = [data[13], data[14], data[15], 255];
+
+        for lazy in [false, true] {
+            let browser = disposed_pixel(CompositingPolicy::BrowserCompatible, lazy);
+            assert_eq!(browser[3], 0);
+
+            let spec = disposed_pixel(CompositingPolicy::SpecStrict, lazy);
+            assert_eq!(spec, bg);
+
+            // Frame 1 has a transparent color, so PIL clears to transparency too
+            let pil = disposed_pixel(CompositingPolicy::PilCompatible, lazy);
+            assert_eq!(pil[3], 0);
+        }
+    }
+
+    /// "Hi" in 8x8 cells on a 16x8 canvas, white on black, shown for 5cs
+    fn plain_text_gif() -> Box<[u8]> {
+        let mut data = b"GIF89a".to_vec();
+        data.extend([16, 0, 8, 0, 0x80, 0, 0]);
+        data.extend([0, 0, 0, 255, 255, 255]);
+        data.extend([0x21, 0xf9, 4, 0, 5, 0, 0, 0]);
+        data.extend([0x21, 0x01, 12, 0, 0, 0, 0, 16, 0, 8, 0, 8, 8, 1, 0]);
+        data.extend([2, b'H', b'i', 0, 0x3b]);
+        data.into()
+    }
+
+    #[test]
+    pub fn plain_text() {
+        let options = DecodeOptions {
+            plain_text: true,
+            ..Default::default()
+        };
+
+        let skipped = decode(plain_text_gif()).unwrap();
+        assert_eq!(skipped.num_frames, 0);
+
+        let decoded = decode_with_options(plain_text_gif(), &options).unwrap();
+        assert_eq!(decoded.num_frames, 1);
+        let frame = decoded.get(0).unwrap();
+        assert_eq!(frame.delay, 5);
+
+        // Top rows of 'H' and 'i'
+        let top_row: Vec<bool> = frame.image_data[..16 * 4]
+            .chunks(4)
+            .map(|px| px == [255, 255, 255, 255])
+            .collect();
+        let expected = [
+            1, 1, 0, 0, 1, 1, 0, 0, //
+            0, 0, 1, 1, 0, 0, 0, 0,
+        ];
+        assert_eq!(top_row, expected.map(|b| b == 1));
+
+        let lazy = decode_lazy_with_options(plain_text_gif(), &options).unwrap();
+        assert_eq!(lazy.get(0).unwrap().image_data, frame.image_data);
+    }
+
+    #[test]
+    pub fn pixel_formats() {
+        let frame_data = |pixel_format, matte_color| {
+            let options = DecodeOptions {
+                pixel_format,
+                matte_color,
+                ..Default::default()
+            };
+            let decoded = read_gif_file_with_options(test_input("dispose2.gif"), &options).unwrap();
+            decoded.get(2).unwrap().image_data.clone()
+        };
+
+        let rgba = frame_data(PixelFormat::Rgba8, 0);
+        assert!(rgba.chunks(4).any(|px| px[3] == 0));
+        assert!(rgba.chunks(4).any(|px| px[3] == 255));
+
+        assert_eq!(frame_data(PixelFormat::PremultipliedRgba8, 0), rgba);
+
+        let bgra = frame_data(PixelFormat::Bgra8, 0);
+        for (s, d) in rgba.chunks(4).zip(bgra.chunks(4)) {
+            assert_eq!([s[2], s[1], s[0], s[3]], d);
+        }
+
+        let rgb = frame_data(PixelFormat::Rgb8, 0x123456);
+        let gray = frame_data(PixelFormat::Gray8, 0xffffff);
+        assert_eq!(rgb.len(), rgba.len() / 4 * 3);
+        assert_eq!(gray.len(), rgba.len() / 4);
+        for ((s, d), g) in rgba.chunks(4).zip(rgb.chunks(3)).zip(gray.iter().copied()) {
+            if s[3] == 0 {
+                assert_eq!(d, [0x12, 0x34, 0x56]);
+                assert_eq!(g, 255);
+            } else {
+                assert_eq!(d, &s[..3]);
+                if s[0] == s[1] && s[1] == s[2] {
+                    assert_eq!(g, s[0]);
+                }
+            }
+        }
+    }
+}
+
+mod streaming {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::iter::zip;
+
+    use gif_controls_decoder::{FrameStream, PushDecoder};
+
+    use crate::util::*;
+
+    /// Frames yielded one at a time should match the ones from `decode()`
+    #[test]
+    pub fn stream_matches_decode() {
+        let f = File::open(test_input("dispose3.gif")).unwrap();
+        let stream = FrameStream::new(BufReader::new(f)).unwrap();
+        let expected = read_bin_file(test_output("dispose3.bin.xz"));
+
+        let mut count = 0;
+        for (frame, image) in zip(stream, &expected) {
+            assert_eq!(
+                frame.unwrap().image_data.as_ref(),
+                image.pixel_data.as_slice()
+            );
+            count += 1;
+        }
+        assert_eq!(count, expected.len());
+    }
+
+    #[test]
+    pub fn stream_is_lazy() {
+        let f = File::open(test_input("earth.gif")).unwrap();
+        let mut stream = FrameStream::new(BufReader::new(f)).unwrap();
+        assert_eq!(stream.canvas_width(), 400);
+
+        let first = stream.next().unwrap().unwrap();
+        assert_eq!(first.image_data.len(), 400 * 400 * 4);
+    }
+
+    /// Feeding the file in small, uneven chunks should produce the same frames
+    #[test]
+    pub fn push_decoder_chunks() {
+        let data = std::fs::read(test_input("dispose2.gif")).unwrap();
+        let expected = read_gif_file(test_input("dispose2.gif")).unwrap();
+
+        let mut decoder = PushDecoder::new();
+        let mut frames = vec![];
+        for chunk in data.chunks(37) {
+            frames.extend(decoder.push(chunk).unwrap());
+        }
+        decoder.finish().unwrap();
+
+        assert_eq!(frames.len(), expected.num_frames);
+        for (frame, expected) in zip(&frames, expected.frames().map(Result::unwrap)) {
+            assert_eq!(frame.image_data, expected.image_data);
+        }
+    }
+
+    #[test]
+    pub fn push_decoder_truncated() {
+        let data = std::fs::read(test_input("dispose2.gif")).unwrap();
+
+        let mut decoder = PushDecoder::new();
+        assert!(decoder.canvas_width().is_none());
+        decoder.push(&data[..data.len() / 2]).unwrap();
+        assert!(decoder.canvas_width().is_some());
+        assert!(decoder.finish().is_err());
+    }
+}
+
+mod lazy_decoding {
+    use crate::util::*;
+
+    #[test]
+    pub fn lazy_matches_expected() {
+        for name in ["dispose1", "dispose2", "dispose3", "local-color-table"] {
+            let decoded = read_gif_file_lazy(test_input(format!("{name}.gif"))).unwrap();
+            let expected = read_bin_file(test_output(format!("{name}.bin.xz")));
+            compare_frames(&decoded, &expected);
+        }
+    }
+
+    /// Jumping around should give the same frames as playing through in order
+    #[test]
+    pub fn lazy_random_access() {
+        let lazy = read_gif_file_lazy(test_input("1bpp.gif")).unwrap();
+        let eager = read_gif_file(test_input("1bpp.gif")).unwrap();
+        compare_meta(&lazy, test_output("1bpp.json"));
+
+        for i in [40, 3, 4, 5, 60, 0, 59] {
+            let frame = lazy.get(i).unwrap();
+            assert_eq!(
+                frame.image_data,
+                eager.get(i).unwrap().image_data,
+                "frame {i}"
+            );
+        }
+        assert!(lazy.get(61).is_err());
+    }
+
+    #[test]
+    pub fn pinned_frames() {
+        let mut lazy = read_gif_file_lazy(test_input("1bpp.gif")).unwrap();
+        let expected = lazy.get(30).unwrap().image_data;
+
+        assert_eq!(lazy.pin(30).unwrap(), &*expected);
+        // Pinning again lends out the same buffer
+        let ptr = lazy.pin(30).unwrap().as_ptr();
+        assert_eq!(lazy.pin(30).unwrap().as_ptr(), ptr);
+
+        assert!(lazy.pin(61).is_err());
+        assert!(lazy.release(30));
+        assert!(!lazy.release(30));
+    }
+}
+
 mod invalid_gifs {
     use std::io;
 
-    use gif_controls_decoder::DecodeError;
+    use gif_controls_decoder::{
+        decode_lazy_with_options, decode_with_options, DecodeError, DecodeOptions, PushDecoder,
+        WarningKind,
+    };
 
     use crate::util::*;
 
+    fn recover() -> DecodeOptions {
+        DecodeOptions {
+            recover: true,
+            ..Default::default()
+        }
+    }
+
+    /// dispose2.gif, cut off partway through the image data of frame 59 (of 62)
+    fn truncated_data() -> Box<[u8]> {
+        let data = std::fs::read(test_input("dispose2.gif")).unwrap();
+        data[..250340].into()
+    }
+
+    #[test]
+    pub fn recover_truncated() {
+        let full = read_gif_file(test_input("dispose2.gif")).unwrap();
+        let decoded = decode_with_options(truncated_data(), &recover()).unwrap();
+
+        assert_eq!(decoded.num_frames, 60);
+        assert_eq!(decoded.warnings[0].kind, WarningKind::Truncated);
+        assert_eq!(decoded.warnings[0].frame, 59);
+        for i in 0..59 {
+            let frame = decoded.get(i).unwrap();
+            assert_eq!(
+                frame.image_data,
+                full.get(i).unwrap().image_data,
+                "frame {i}"
+            );
+        }
+
+        let lazy = decode_lazy_with_options(truncated_data(), &recover()).unwrap();
+        assert_eq!(lazy.num_frames, 60);
+        assert_eq!(lazy.warnings[0], decoded.warnings[0]);
+        assert_eq!(
+            lazy.get(59).unwrap().image_data,
+            decoded.get(59).unwrap().image_data
+        );
+
+        match decode_with_options(truncated_data(), &DecodeOptions::default()) {
+            Err(DecodeError::IO(ioe)) => assert_eq!(ioe.kind(), io::ErrorKind::UnexpectedEof),
+            _ => panic!("Truncated file should fail without recovery"),
+        }
+    }
+
+    #[test]
+    pub fn recover_truncated_push() {
+        let mut decoder = PushDecoder::with_options(&recover());
+        let mut count = decoder.push(&truncated_data()).unwrap().len();
+        count += decoder.finish().unwrap().len();
+        let decoded = decode_with_options(truncated_data(), &recover()).unwrap();
+
+        assert_eq!(count, 60);
+        assert_eq!(decoder.warnings(), decoded.warnings);
+    }
+
+    /// Junk bytes between blocks should be skipped, with a single warning
+    #[test]
+    pub fn recover_unknown_block() {
+        let mut data = std::fs::read(test_input("dispose1.gif")).unwrap();
+        let trailer = data.pop().unwrap();
+        data.extend([0x99, 0x98, 0x97, trailer]);
+
+        let decoded = decode_with_options(data.clone().into(), &recover()).unwrap();
+        let skipped: Vec<_> = decoded
+            .warnings
+            .iter()
+            .filter(|w| matches!(w.kind, WarningKind::UnknownBlock(_)))
+            .collect();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].kind, WarningKind::UnknownBlock(0x99));
+        assert_eq!(skipped[0].offset, data.len() as u64 - 4);
+        assert_eq!(skipped[0].frame, 62);
+        compare_frames(&decoded, &read_bin_file(test_output("dispose1.bin.xz")));
+
+        match decode_with_options(data.into(), &DecodeOptions::default()) {
+            Err(DecodeError::UnknownBlock(0x99)) => {}
+            _ => panic!("Unknown block should fail without recovery"),
+        }
+    }
+
     /// Ensure the decoder doesn't crash if a frame has neither a global nor local color table
     #[test]
     pub fn no_crash_on_missing_color_table() {
@@ -102,3 +524,1582 @@ mod invalid_gifs {
         }
     }
 }
+
+mod warnings {
+    use gif_controls_decoder::{
+        decode, decode_lazy, decode_lazy_with_options, decode_with_options, DecodeOptions,
+        PushDecoder, WarningKind,
+    };
+
+    use crate::util::*;
+
+    #[test]
+    pub fn clean_file_has_no_warnings() {
+        let decoded = decode(tiny_gif(&[], true).into()).unwrap();
+        assert_eq!(decoded.num_frames, 1);
+        assert!(decoded.warnings.is_empty());
+    }
+
+    #[test]
+    pub fn malformed_graphics_control() {
+        let data = tiny_gif(&[0x21, 0xf9, 0x02, 0, 0, 0], true);
+        let decoded = decode(data.into()).unwrap();
+
+        assert_eq!(decoded.warnings.len(), 1);
+        let warning = &decoded.warnings[0];
+        assert_eq!(warning.kind, WarningKind::MalformedGraphicsControl);
+        assert_eq!(warning.offset, 19);
+        assert_eq!(warning.frame, 0);
+    }
+
+    #[test]
+    pub fn malformed_netscape_extension() {
+        let mut ext = vec![0x21, 0xff, 0x0b];
+        ext.extend(b"NETSCAPE2.0");
+        ext.extend([0x01, 0x01, 0]);
+        let decoded = decode(tiny_gif(&ext, true).into()).unwrap();
+
+        assert_eq!(decoded.max_loops, None);
+        let kinds: Vec<_> = decoded.warnings.iter().map(|w| &w.kind).collect();
+        assert_eq!(kinds, [&WarningKind::MalformedNetscapeExtension]);
+    }
+
+    #[test]
+    pub fn missing_trailer() {
+        let data = tiny_gif(&[], false);
+        let len = data.len() as u64;
+
+        let decoded = decode(data.clone().into()).unwrap();
+        assert_eq!(decoded.warnings.len(), 1);
+        assert_eq!(decoded.warnings[0].kind, WarningKind::MissingTrailer);
+        assert_eq!(decoded.warnings[0].offset, len);
+        assert_eq!(decoded.warnings[0].frame, 1);
+
+        let lazy = decode_lazy(data.clone().into()).unwrap();
+        assert_eq!(lazy.warnings, decoded.warnings);
+
+        // Split the file partway through a block, so offsets have to survive the buffer
+        // being drained
+        let mut push = PushDecoder::new();
+        push.push(&data[..20]).unwrap();
+        push.push(&data[20..]).unwrap();
+        push.finish().unwrap();
+        assert_eq!(push.warnings(), decoded.warnings);
+    }
+
+    #[test]
+    pub fn frame_outside_canvas() {
+        // Two 1x1 frames past the right and bottom edges of a 1x1 canvas, where the first one
+        // is restored to the background afterwards
+        let mut data = tiny_gif(&[0x21, 0xf9, 4, 0x08, 0, 0, 0, 0], false);
+        let frame = data.len() - 15;
+        data[frame + 1] = 10;
+        data[frame + 3] = 10;
+        data.extend_from_within(frame..);
+        data.push(0x3b);
+
+        for recover in [false, true] {
+            let options = DecodeOptions {
+                recover,
+                ..Default::default()
+            };
+            let eager = decode_with_options(data.clone().into(), &options).unwrap();
+            let lazy = decode_lazy_with_options(data.clone().into(), &options).unwrap();
+            assert_eq!(eager.num_frames, 2);
+            assert_eq!(eager.warnings[0].kind, WarningKind::FrameOutsideCanvas);
+            for i in 0..2 {
+                assert_eq!(*eager.get(i).unwrap().image_data, [0; 4]);
+                assert_eq!(*lazy.get(i).unwrap().image_data, [0; 4]);
+            }
+        }
+    }
+
+    #[test]
+    pub fn missing_color_table() {
+        let decoded = read_gif_file(test_input("earth-bad-color-table.gif")).unwrap();
+        // Only the first frame has a local color table
+        assert_eq!(decoded.warnings.len(), decoded.num_frames - 1);
+        for (i, warning) in decoded.warnings.iter().enumerate() {
+            assert_eq!(warning.kind, WarningKind::MissingColorTable);
+            assert_eq!(warning.frame, i + 1);
+        }
+    }
+}
+
+mod comments {
+    use gif_controls_decoder::{decode, decode_lazy, FrameStream, GifComment};
+
+    use crate::util::*;
+
+    #[test]
+    pub fn comments_with_frame_index() {
+        // Split across two sub-blocks, with an invalid UTF-8 byte
+        let mut data = tiny_gif(&[0x21, 0xfe, 3, b'a', b'b', 0xff, 2, b'c', b'd', 0], false);
+        data.extend([0x21, 0xfe, 4]);
+        data.extend(b"last");
+        data.extend([0, 0x3b]);
+
+        let expected = vec![
+            GifComment {
+                frame: 0,
+                text: "ab\u{fffd}cd".into(),
+            },
+            GifComment {
+                frame: 1,
+                text: "last".into(),
+            },
+        ];
+
+        let decoded = decode(data.clone().into()).unwrap();
+        assert_eq!(decoded.comments, expected);
+
+        let lazy = decode_lazy(data.clone().into()).unwrap();
+        assert_eq!(lazy.comments, expected);
+
+        let mut stream = FrameStream::new(data.as_slice()).unwrap();
+        stream.next().unwrap().unwrap();
+        assert_eq!(stream.comments(), &expected[..1]);
+        assert!(stream.next().is_none());
+        assert_eq!(stream.comments(), expected);
+    }
+}
+
+mod extensions {
+    use gif_controls_decoder::{
+        decode, decode_lazy, decode_lazy_with_options, decode_with_options, ApplicationExtension,
+        DecodeOptions, WarningKind,
+    };
+
+    use crate::util::*;
+
+    fn app_ext(app: &[u8; 11], blocks: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![0x21, 0xff, 11];
+        data.extend(app);
+        for block in blocks {
+            data.push(block.len() as u8);
+            data.extend(*block);
+        }
+        data.push(0);
+        data
+    }
+
+    #[test]
+    pub fn xmp_packet() {
+        // Longer than a sub-block, so the decoder reads it as several
+        let packet = format!("<x:xmpmeta>{}</x:xmpmeta>", "rights ".repeat(50));
+        let mut ext = vec![0x21, 0xff, 11];
+        ext.extend(b"XMP DataXMP");
+        ext.extend(packet.as_bytes());
+        ext.push(1);
+        ext.extend((0..=255).rev());
+        ext.push(0);
+
+        let decoded = decode(tiny_gif(&ext, true).into()).unwrap();
+        assert_eq!(decoded.xmp(), Some(packet.as_str()));
+        assert!(decoded.warnings.is_empty());
+    }
+
+    #[test]
+    pub fn icc_profile() {
+        let profile: Vec<u8> = (0..600).map(|i| i as u8).collect();
+        let blocks: Vec<&[u8]> = profile.chunks(255).collect();
+        let ext = app_ext(b"ICCRGBG1012", &blocks);
+
+        let decoded = decode_lazy(tiny_gif(&ext, true).into()).unwrap();
+        assert_eq!(decoded.icc_profile(), Some(profile.as_slice()));
+    }
+
+    #[test]
+    pub fn animation_and_unknown() {
+        let mut exts = app_ext(b"ANIMEXTS1.0", &[&[1, 3, 0]]);
+        exts.extend(app_ext(b"NETSCAPE2.0", &[&[2, 0, 16, 0, 0]]));
+        exts.extend(app_ext(b"MGK8BIM0000", &[b"abc", b"de"]));
+
+        let decoded = decode(tiny_gif(&exts, true).into()).unwrap();
+        assert_eq!(decoded.max_loops, Some(3));
+        assert!(decoded.warnings.is_empty());
+        assert_eq!(
+            decoded.application_extensions,
+            [
+                ApplicationExtension::Animation {
+                    animexts: true,
+                    loops: Some(3),
+                    buffer_size: None,
+                },
+                ApplicationExtension::Animation {
+                    animexts: false,
+                    loops: None,
+                    buffer_size: Some(4096),
+                },
+                ApplicationExtension::Unknown {
+                    identifier: *b"MGK8BIM0",
+                    auth_code: *b"000",
+                    data: b"abcde".to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn unknown_animation_sub_block() {
+        let ext = app_ext(b"NETSCAPE2.0", &[&[1, 5, 0], &[7]]);
+        let decoded = decode(tiny_gif(&ext, true).into()).unwrap();
+
+        assert_eq!(decoded.max_loops, Some(5));
+        assert_eq!(
+            decoded.warnings[0].kind,
+            WarningKind::MalformedNetscapeExtension
+        );
+    }
+
+    /// An RGB matrix/TRC profile with sRGB's primaries, and `trc` as every channel's curve
+    fn matrix_profile(trc: &[u8]) -> Vec<u8> {
+        let xyz = |values: [f64; 3]| {
+            let mut tag = b"XYZ \0\0\0\0".to_vec();
+            for v in values {
+                tag.extend(((v * 65536.0).round() as i32).to_be_bytes());
+            }
+            tag
+        };
+        let tags = [
+            (b"rXYZ", xyz([0.4360747, 0.2225045, 0.0139322])),
+            (b"gXYZ", xyz([0.3850649, 0.7168786, 0.0971045])),
+            (b"bXYZ", xyz([0.1430804, 0.0606169, 0.7141733])),
+            (b"rTRC", trc.to_vec()),
+            (b"gTRC", trc.to_vec()),
+            (b"bTRC", trc.to_vec()),
+        ];
+
+        let mut header = vec![0; 128];
+        header[16..20].copy_from_slice(b"RGB ");
+        header[20..24].copy_from_slice(b"XYZ ");
+
+        let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut data = vec![];
+        let data_start = header.len() + 4 + tags.len() * 12;
+        for (sig, tag) in &tags {
+            table.extend(*sig);
+            table.extend(((data_start + data.len()) as u32).to_be_bytes());
+            table.extend((tag.len() as u32).to_be_bytes());
+            data.extend(tag);
+        }
+        [header, table, data].concat()
+    }
+
+    /// The color of a 1x1 GIF's only pixel, which is mid-gray before any conversion
+    fn converted_gray(profile: &[u8], color_management: bool) -> (Vec<u8>, Vec<WarningKind>) {
+        let blocks: Vec<&[u8]> = profile.chunks(255).collect();
+        let mut data = tiny_gif(&app_ext(b"ICCRGBG1012", &blocks), true);
+        data[13..16].copy_from_slice(&[128, 128, 128]);
+
+        let options = DecodeOptions {
+            color_management,
+            ..Default::default()
+        };
+        let decoded = decode_with_options(data.into(), &options).unwrap();
+        let pixel = decoded.get(0).unwrap().image_data.to_vec();
+        let warnings = decoded.warnings.into_iter().map(|w| w.kind).collect();
+        (pixel, warnings)
+    }
+
+    #[test]
+    pub fn color_management() {
+        // Without the option, colors pass through
+        let linear = matrix_profile(b"curv\0\0\0\0\0\0\0\0");
+        assert_eq!(converted_gray(&linear, false).0, [128, 128, 128, 255]);
+
+        // Linear 50% gray is about 188 in sRGB
+        let (pixel, warnings) = converted_gray(&linear, true);
+        assert!(warnings.is_empty());
+        for channel in &pixel[..3] {
+            assert!(channel.abs_diff(188) <= 1, "{pixel:?}");
+        }
+
+        // sRGB's own curve, as a parametric curve, shouldn't change anything
+        let mut srgb = b"para\0\0\0\0\0\x03\0\0".to_vec();
+        for v in [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045] {
+            srgb.extend(((v * 65536.0f64).round() as i32).to_be_bytes());
+        }
+        let (pixel, _) = converted_gray(&matrix_profile(&srgb), true);
+        for channel in &pixel[..3] {
+            assert!(channel.abs_diff(128) <= 1, "{pixel:?}");
+        }
+    }
+
+    #[test]
+    pub fn color_profile_after_first_frame() {
+        // Two gray frames with the profile between them
+        let linear = matrix_profile(b"curv\0\0\0\0\0\0\0\0");
+        let blocks: Vec<&[u8]> = linear.chunks(255).collect();
+        let mut data = tiny_gif(&[], false);
+        data[13..16].copy_from_slice(&[128, 128, 128]);
+        let frame = data[19..].to_vec();
+        data.extend(app_ext(b"ICCRGBG1012", &blocks));
+        data.extend(frame);
+        data.push(0x3b);
+
+        let options = DecodeOptions {
+            color_management: true,
+            ..Default::default()
+        };
+        let eager = decode_with_options(data.clone().into(), &options).unwrap();
+        let lazy = decode_lazy_with_options(data.into(), &options).unwrap();
+        assert_eq!(eager.num_frames, 2);
+
+        // Only the frame after the profile is converted, however the frames are decoded
+        for i in [1, 0, 1] {
+            let pixel = eager.get(i).unwrap().image_data;
+            assert_eq!(pixel, lazy.get(i).unwrap().image_data, "frame {i}");
+            let expected = if i == 0 { 128 } else { 188 };
+            for channel in &pixel[..3] {
+                assert!(channel.abs_diff(expected) <= 1, "frame {i}: {pixel:?}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn unsupported_color_profile() {
+        let mut profile = matrix_profile(b"curv\0\0\0\0\0\0\0\0");
+        profile[16..20].copy_from_slice(b"GRAY");
+
+        let (pixel, warnings) = converted_gray(&profile, true);
+        assert_eq!(pixel, [128, 128, 128, 255]);
+        assert_eq!(warnings, [WarningKind::UnsupportedColorProfile]);
+    }
+}
+
+mod indexed {
+    use gif_controls_decoder::{
+        decode_indexed, decode_indexed_with_options, decode_with_options, encode, DecodeOptions,
+        EncodeFrame, EncodeOptions, FramePixels, IndexedFrame, WarningKind,
+    };
+
+    use crate::util::*;
+
+    /// Looks up every index, for frames without transparency
+    fn to_rgba(frame: &IndexedFrame) -> Vec<u8> {
+        frame
+            .indices
+            .iter()
+            .flat_map(|&i| {
+                let i = usize::from(i) * 3;
+                [
+                    frame.palette[i],
+                    frame.palette[i + 1],
+                    frame.palette[i + 2],
+                    255,
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn indexed_matches_rgba() {
+        for name in ["interlaced.gif", "dispose1.gif"] {
+            let data = std::fs::read(test_input(name)).unwrap();
+            let rgba = read_gif_file(test_input(name)).unwrap();
+            let indexed = decode_indexed(data.into()).unwrap();
+
+            assert_eq!(indexed.frames.len(), rgba.num_frames, "{name}");
+            let first = &indexed.frames[0];
+            assert_eq!(first.transparent, None);
+            assert_eq!(to_rgba(first), &*rgba.get(0).unwrap().image_data, "{name}");
+        }
+    }
+
+    #[test]
+    pub fn indexed_palettes() {
+        let data = std::fs::read(test_input("dispose1.gif")).unwrap();
+        let rgba = read_gif_file(test_input("dispose1.gif")).unwrap();
+        let indexed = decode_indexed(data.into()).unwrap();
+
+        let frame = &indexed.frames[1];
+        assert_eq!(
+            (frame.left, frame.top, frame.width, frame.height),
+            (5, 15, 100, 145)
+        );
+        assert_eq!(frame.indices.len(), 100 * 145);
+        assert_eq!(frame.transparent, Some(29));
+        assert_eq!(
+            Some(&frame.palette),
+            rgba.get(1).unwrap().local_palette.as_ref()
+        );
+    }
+
+    #[test]
+    pub fn out_of_bounds_indices() {
+        // A frame using 4 colors, with its color table cut down to 2 afterwards
+        let frame = EncodeFrame {
+            pixels: FramePixels::Indexed {
+                indices: [0, 1, 2, 3].into(),
+                palette: (0..12).collect(),
+                transparent: Some(2),
+            },
+            ..EncodeFrame::rgba(2, 2, Box::new([]), 0)
+        };
+        let mut data = encode(2, 2, &[frame], &EncodeOptions::default()).unwrap();
+        let descriptor = data.iter().position(|&b| b == 0x2c).unwrap();
+        data[descriptor + 9] = 0x80;
+        data.drain(descriptor + 16..descriptor + 22);
+
+        let options = DecodeOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let rgba = decode_with_options(data.clone().into(), &options).unwrap();
+        let indexed = decode_indexed_with_options(data.into(), &options).unwrap();
+        assert!(indexed
+            .warnings
+            .iter()
+            .any(|w| w.kind == WarningKind::ColorTableOutOfBounds));
+
+        // Shown as the last color, the same as when decoding to RGBA
+        let frame = &indexed.frames[0];
+        assert_eq!(frame.transparent, Some(2));
+        assert_eq!(*frame.palette, [0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4, 5]);
+        let image = rgba.get(0).unwrap().image_data;
+        assert_eq!(&image[4..8], &[3, 4, 5, 255]);
+        assert_eq!(&image[8..12], &[0, 0, 0, 0]);
+        assert_eq!(&image[12..16], &[3, 4, 5, 255]);
+
+        // Which can be encoded again as is
+        let frames = [EncodeFrame::from(frame.clone())];
+        assert!(encode(2, 2, &frames, &EncodeOptions::default()).is_ok());
+    }
+}
+
+mod encoding {
+    use gif_controls_decoder::{
+        decode, decode_indexed, encode, export_range, export_retimed, DecodedGif, EncodeError,
+        EncodeFrame, EncodeOptions, FrameOrder, FramePixels, RetimeOptions,
+    };
+
+    use crate::util::*;
+
+    #[test]
+    pub fn indexed_round_trip() {
+        for name in [
+            "interlaced.gif",
+            "1bpp.gif",
+            "local-color-table.gif",
+            "earth-transparent.gif",
+        ] {
+            for interlace in [false, true] {
+                let data = std::fs::read(test_input(name)).unwrap();
+                let original = decode_indexed(data.clone().into()).unwrap();
+                let options = EncodeOptions {
+                    loops: original.max_loops,
+                    interlace,
+                };
+
+                let frames: Vec<EncodeFrame> =
+                    original.frames.iter().cloned().map(Into::into).collect();
+                let encoded = encode(
+                    original.canvas_width,
+                    original.canvas_height,
+                    &frames,
+                    &options,
+                )
+                .unwrap();
+
+                let reencoded = decode_indexed(encoded.clone().into()).unwrap();
+                assert!(reencoded.warnings.is_empty(), "{name}");
+                assert_eq!(reencoded.max_loops, original.max_loops, "{name}");
+                assert_eq!(reencoded.frames.len(), original.frames.len(), "{name}");
+                for (a, b) in original.frames.iter().zip(&reencoded.frames) {
+                    assert_eq!(a.indices, b.indices, "{name}");
+                    assert_eq!(*a.palette, b.palette[..a.palette.len()], "{name}");
+                    assert_eq!(
+                        (a.delay, a.disposal_method, a.transparent),
+                        (b.delay, b.disposal_method, b.transparent),
+                        "{name}"
+                    );
+                }
+
+                // Without a global color table the background is black, but restoring to the
+                // background clears to transparency by default, so compositing matches too
+                let original = decode(data.into()).unwrap();
+                let reencoded = decode(encoded.into()).unwrap();
+                for i in 0..original.num_frames {
+                    assert_eq!(
+                        original.get(i).unwrap().image_data,
+                        reencoded.get(i).unwrap().image_data,
+                        "{name} frame {i}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn rgba_quantized() {
+        // Every pixel a different color, with a transparent stripe
+        let (width, height) = (64, 64);
+        let mut rgba = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let alpha = if x < 4 { 0 } else { 255 };
+                rgba.extend([x as u8 * 4, y as u8 * 4, 128, alpha]);
+            }
+        }
+
+        let frame = EncodeFrame::rgba(width, height, rgba.clone().into(), 7);
+        let options = EncodeOptions {
+            loops: Some(0),
+            interlace: false,
+        };
+        let encoded = encode(width, height, &[frame], &options).unwrap();
+
+        let decoded = decode(encoded.clone().into()).unwrap();
+        assert_eq!(decoded.max_loops, Some(0));
+        let out = decoded.get(0).unwrap();
+        assert_eq!(out.delay, 7);
+        for (src, dst) in rgba.chunks(4).zip(out.image_data.chunks(4)) {
+            assert_eq!(src[3], dst[3]);
+            if src[3] == 255 {
+                for ch in 0..3 {
+                    assert!(src[ch].abs_diff(dst[ch]) <= 16, "{src:?} vs {dst:?}");
+                }
+            }
+        }
+
+        let indexed = decode_indexed(encoded.into()).unwrap();
+        assert_eq!(indexed.frames[0].palette.len(), 256 * 3);
+    }
+
+    #[test]
+    pub fn large_noisy_frame() {
+        // Enough incompressible data that the LZW table fills up and is cleared many times
+        let (width, height) = (300, 200);
+        let mut state = 0x1234_5678u32;
+        let indices: Vec<u8> = (0..width * height)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect();
+
+        let frame = EncodeFrame {
+            pixels: FramePixels::Indexed {
+                indices: indices.clone().into(),
+                palette: (0..=255).flat_map(|i| [i, i, i]).collect(),
+                transparent: None,
+            },
+            ..EncodeFrame::rgba(width as u16, height as u16, Box::new([]), 0)
+        };
+        let encoded = encode(
+            width as u16,
+            height as u16,
+            &[frame],
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+
+        let decoded = decode_indexed(encoded.into()).unwrap();
+        assert!(decoded.warnings.is_empty());
+        assert_eq!(decoded.max_loops, None);
+        assert_eq!(&*decoded.frames[0].indices, &indices);
+    }
+
+    #[test]
+    pub fn index_out_of_bounds() {
+        let indexed = |indices: &[u8], transparent| EncodeFrame {
+            pixels: FramePixels::Indexed {
+                indices: indices.into(),
+                palette: [0, 0, 0, 255, 255, 255].into(),
+                transparent,
+            },
+            ..EncodeFrame::rgba(2, 2, Box::new([]), 0)
+        };
+        let options = EncodeOptions::default();
+
+        assert!(encode(2, 2, &[indexed(&[0, 1, 1, 0], Some(1))], &options).is_ok());
+        let result = encode(2, 2, &[indexed(&[0, 1, 3, 0], None)], &options);
+        assert!(matches!(
+            result,
+            Err(EncodeError::IndexOutOfBounds {
+                index: 3,
+                colors: 2
+            })
+        ));
+        let result = encode(2, 2, &[indexed(&[0, 1, 1, 0], Some(2))], &options);
+        assert!(matches!(
+            result,
+            Err(EncodeError::IndexOutOfBounds { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    pub fn export_ranges() {
+        // dispose1 has a frame outside the canvas at 61
+        let cases = [
+            ("dispose1.gif", 50, 70),
+            ("dispose2.gif", 10, 30),
+            ("dispose3.gif", 5, 62),
+            ("earth-transparent.gif", 3, 9),
+        ];
+        for (name, start, end) in cases {
+            let data = std::fs::read(test_input(name)).unwrap();
+            let original = decode(data.clone().into()).unwrap();
+            let exported =
+                export_range(data.into(), start, end, &EncodeOptions::default()).unwrap();
+            let exported = decode(exported.into()).unwrap();
+
+            let end = end.min(original.num_frames);
+            assert_eq!(exported.num_frames, end - start, "{name}");
+            for i in start..end {
+                let (a, b) = (original.get(i).unwrap(), exported.get(i - start).unwrap());
+                assert_eq!(a.delay, b.delay, "{name} frame {i}");
+                assert!(a.image_data == b.image_data, "{name} frame {i}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn export_copies_later_frames() {
+        let data = std::fs::read(test_input("earth-transparent.gif")).unwrap();
+        let original = decode_indexed(data.clone().into()).unwrap();
+        let exported = export_range(data.into(), 3, 9, &EncodeOptions::default()).unwrap();
+        let exported = decode_indexed(exported.into()).unwrap();
+
+        for i in 1..6 {
+            let (a, b) = (&original.frames[i + 3], &exported.frames[i]);
+            assert_eq!(a.indices, b.indices);
+            assert_eq!(a.transparent, b.transparent);
+        }
+    }
+
+    #[test]
+    pub fn export_empty_range() {
+        let data = std::fs::read(test_input("earth.gif")).unwrap();
+        let options = EncodeOptions::default();
+        for (start, end) in [(5, 5), (7, 3), (1000, 1010)] {
+            let result = export_range(data.clone().into(), start, end, &options);
+            assert!(matches!(result, Err(EncodeError::InvalidRange { .. })));
+        }
+    }
+
+    fn retimed(name: &str, retime: RetimeOptions) -> (DecodedGif, DecodedGif) {
+        let data = std::fs::read(test_input(name)).unwrap();
+        let original = decode(data.clone().into()).unwrap();
+        let exported = export_retimed(data.into(), &retime, &EncodeOptions::default()).unwrap();
+        (original, decode(exported.into()).unwrap())
+    }
+
+    #[test]
+    pub fn reversed_and_ping_pong() {
+        for name in ["dispose2.gif", "dispose3.gif", "earth-transparent.gif"] {
+            for order in [FrameOrder::Reverse, FrameOrder::PingPong] {
+                let retime = RetimeOptions {
+                    order,
+                    ..Default::default()
+                };
+                let (original, exported) = retimed(name, retime);
+
+                let n = original.num_frames;
+                let expected: Vec<usize> = match order {
+                    FrameOrder::Reverse => (0..n).rev().collect(),
+                    _ => (0..n).chain((1..n - 1).rev()).collect(),
+                };
+                assert_eq!(exported.num_frames, expected.len(), "{name}");
+                for (k, &i) in expected.iter().enumerate() {
+                    let (a, b) = (original.get(i).unwrap(), exported.get(k).unwrap());
+                    assert_eq!(a.delay, b.delay, "{name} frame {i}");
+                    assert!(a.image_data == b.image_data, "{name} {order:?} frame {i}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn speed_rounding() {
+        // Every frame of earth.gif is shown for 9cs
+        let retime = RetimeOptions {
+            speed: 2.0,
+            ..Default::default()
+        };
+        let (original, exported) = retimed("earth.gif", retime);
+        assert_eq!(exported.num_frames, original.num_frames);
+
+        let delays: Vec<u16> = exported.frames().map(|f| f.unwrap().delay).collect();
+        assert!(delays.iter().all(|&d| d == 4 || d == 5), "{delays:?}");
+        let total: u16 = delays.iter().sum();
+        assert_eq!(total, 44 * 9 / 2);
+    }
+
+    #[test]
+    pub fn speed_merges_short_frames() {
+        let retime = RetimeOptions {
+            speed: 6.0,
+            ..Default::default()
+        };
+        let (original, exported) = retimed("earth.gif", retime);
+
+        // 1.5cs per frame, so some frames are merged into the next to reach 2cs
+        let mut shown_until = 0.0;
+        let mut k = 0;
+        for i in 0..original.num_frames {
+            let end = ((i + 1) as f64 * 1.5).round();
+            if end - shown_until < 2.0 && i + 1 < original.num_frames {
+                continue;
+            }
+
+            let frame = exported.get(k).unwrap();
+            // The last frame can't be merged, so it's lengthened instead
+            assert_eq!(f64::from(frame.delay), (end - shown_until).max(2.0));
+            assert!(frame.image_data == original.get(i).unwrap().image_data);
+            shown_until = end;
+            k += 1;
+        }
+        assert_eq!(exported.num_frames, k);
+    }
+
+    #[test]
+    pub fn retime_keeps_user_input() {
+        // A black frame, then a white one that waits for input
+        let mut data = tiny_gif(&[0x21, 0xf9, 4, 0, 10, 0, 0, 0], false);
+        data.extend([0x21, 0xf9, 4, 0b10, 10, 0, 0, 0]);
+        data.extend([0x2c, 0, 0, 0, 0, 1, 0, 1, 0, 0]);
+        data.extend([2, 2, 0x4c, 0x01, 0, 0x3b]);
+
+        for (order, expected) in [
+            (FrameOrder::Forward, [false, true]),
+            (FrameOrder::Reverse, [true, false]),
+        ] {
+            let retime = RetimeOptions {
+                order,
+                ..Default::default()
+            };
+            let exported =
+                export_retimed(data.clone().into(), &retime, &EncodeOptions::default()).unwrap();
+            let exported = decode(exported.into()).unwrap();
+            let user_input: Vec<bool> = exported.frames().map(|f| f.unwrap().user_input).collect();
+            assert_eq!(user_input, expected, "{order:?}");
+        }
+    }
+
+    #[test]
+    pub fn retime_empty_canvas() {
+        // A 0x0 canvas, with a 1x1 frame hanging off the edge of it
+        let mut data = tiny_gif(&[], true);
+        data[6..10].copy_from_slice(&[0; 4]);
+        let original = decode(data.clone().into()).unwrap();
+        assert_eq!(original.num_frames, 1);
+
+        for order in [FrameOrder::Forward, FrameOrder::Reverse] {
+            let retime = RetimeOptions {
+                order,
+                ..Default::default()
+            };
+            let exported =
+                export_retimed(data.clone().into(), &retime, &EncodeOptions::default()).unwrap();
+            let exported = decode(exported.into()).unwrap();
+            assert_eq!((exported.canvas_width, exported.canvas_height), (0, 0));
+            assert_eq!(exported.num_frames, 1);
+            assert!(exported.get(0).unwrap().image_data.is_empty());
+        }
+    }
+
+    #[test]
+    pub fn invalid_speed() {
+        let data = std::fs::read(test_input("earth.gif")).unwrap();
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let retime = RetimeOptions {
+                speed,
+                ..Default::default()
+            };
+            let result = export_retimed(data.clone().into(), &retime, &EncodeOptions::default());
+            assert!(matches!(result, Err(EncodeError::InvalidSpeed(_))));
+        }
+    }
+}
+
+mod png_export {
+    use gif_controls_decoder::{
+        decode, decode_lazy, decode_with_options, DecodeLimits, DecodeOptions, DecodedGif,
+        EncodeError, PixelFormat,
+    };
+
+    use crate::util::png::{be_u32, decode_image, read_chunks, read_png};
+    use crate::util::*;
+
+    /// Composites an APNG the way a viewer would, returning each frame's canvas and delay.
+    fn play_apng(png: &[u8]) -> (u32, Vec<(Vec<u8>, u16)>) {
+        let chunks = read_chunks(png);
+        assert_eq!(&chunks[0].kind, b"IHDR");
+        let width = be_u32(&chunks[0].data, 0) as usize;
+        let height = be_u32(&chunks[0].data, 4) as usize;
+        assert_eq!(&chunks[1].kind, b"acTL");
+        let num_frames = be_u32(&chunks[1].data, 0) as usize;
+        let plays = be_u32(&chunks[1].data, 4);
+
+        let mut canvas = vec![0; width * height * 4];
+        let mut frames = vec![];
+        let mut sequence = 0;
+        let mut i = 2;
+        while &chunks[i].kind == b"fcTL" {
+            let fctl = &chunks[i].data;
+            assert_eq!(be_u32(fctl, 0), sequence);
+            sequence += 1;
+            let [w, h, x, y] = [4, 8, 12, 16].map(|o| be_u32(fctl, o) as usize);
+            let delay = u16::from_be_bytes([fctl[20], fctl[21]]);
+            assert_eq!(u16::from_be_bytes([fctl[22], fctl[23]]), 100);
+            let (dispose_op, blend_op) = (fctl[24], fctl[25]);
+            assert_eq!(blend_op, 1);
+
+            let data = &chunks[i + 1];
+            let image = match &data.kind {
+                b"IDAT" => decode_image(w, h, 4, &data.data),
+                b"fdAT" => {
+                    assert_eq!(be_u32(&data.data, 0), sequence);
+                    sequence += 1;
+                    decode_image(w, h, 4, &data.data[4..])
+                }
+                kind => panic!("unexpected chunk {kind:?}"),
+            };
+            i += 2;
+
+            let before = canvas.clone();
+            for row in 0..h {
+                for col in 0..w {
+                    let src = &image[(row * w + col) * 4..][..4];
+                    if src[3] != 0 {
+                        let dest = ((y + row) * width + x + col) * 4;
+                        canvas[dest..dest + 4].copy_from_slice(src);
+                    }
+                }
+            }
+            frames.push((canvas.clone(), delay));
+
+            match dispose_op {
+                0 => {}
+                1 => {
+                    for row in y..y + h {
+                        canvas[(row * width + x) * 4..(row * width + x + w) * 4].fill(0);
+                    }
+                }
+                _ => canvas = before,
+            }
+        }
+
+        assert_eq!(frames.len(), num_frames);
+        (plays, frames)
+    }
+
+    fn check_frames(gif: &DecodedGif, name: &str) {
+        let (_, frames) = play_apng(&gif.to_apng().unwrap());
+        assert_eq!(frames.len(), gif.num_frames, "{name}");
+        for (i, (canvas, delay)) in frames.iter().enumerate() {
+            let frame = gif.get(i).unwrap();
+            let expected = if frame.delay < 2 { 10 } else { frame.delay };
+            assert_eq!(*delay, expected, "{name} frame {i}");
+            assert!(**canvas == *frame.image_data, "{name} frame {i}");
+        }
+    }
+
+    #[test]
+    pub fn apng_matches_gif() {
+        // dispose1 has a frame outside the canvas, and the earth has transparency
+        for name in ["dispose1.gif", "earth-transparent.gif"] {
+            check_frames(&read_gif_file(test_input(name)).unwrap(), name);
+        }
+        let lazy = read_gif_file_lazy(test_input("dispose1.gif")).unwrap();
+        check_frames(&lazy, "dispose1.gif");
+    }
+
+    #[test]
+    pub fn apng_loop_counts() {
+        let netscape = |loops: u16| {
+            let mut ext = vec![0x21, 0xff, 11];
+            ext.extend(b"NETSCAPE2.0");
+            ext.extend([3, 1]);
+            ext.extend(loops.to_le_bytes());
+            ext.push(0);
+            ext
+        };
+
+        for (ext, expected) in [(vec![], 1), (netscape(0), 0), (netscape(2), 3)] {
+            let gif = decode(tiny_gif(&ext, true).into()).unwrap();
+            let (plays, frames) = play_apng(&gif.to_apng().unwrap());
+            assert_eq!(plays, expected);
+            assert_eq!(frames[0].0, &*gif.get(0).unwrap().image_data);
+        }
+    }
+
+    #[test]
+    pub fn apng_no_frames() {
+        let mut data = b"GIF89a".to_vec();
+        data.extend([1, 0, 1, 0, 0x80, 0, 0, 0, 0, 0, 255, 255, 255, 0x3b]);
+        let gif = decode(data.into()).unwrap();
+        assert_eq!(gif.num_frames, 0);
+        assert!(matches!(gif.to_apng(), Err(EncodeError::NoFrames)));
+    }
+
+    #[test]
+    pub fn frame_pngs() {
+        let gif = read_gif_file(test_input("dispose2.gif")).unwrap();
+        let (width, height, color_type, pixels) = read_png(&gif.frame_png(5).unwrap());
+        assert_eq!((width, height), (600, 400));
+        assert_eq!(color_type, 6);
+        assert!(pixels == *gif.get(5).unwrap().image_data);
+
+        for (format, expected_type) in [
+            (PixelFormat::Bgra8, 6),
+            (PixelFormat::Rgb8, 2),
+            (PixelFormat::Gray8, 0),
+        ] {
+            let options = DecodeOptions {
+                pixel_format: format,
+                ..Default::default()
+            };
+            let other = read_gif_file_with_options(test_input("dispose2.gif"), &options).unwrap();
+            let (_, _, color_type, other_pixels) = read_png(&other.frame_png(5).unwrap());
+            assert_eq!(color_type, expected_type);
+            if format == PixelFormat::Bgra8 {
+                assert!(other_pixels == pixels);
+            } else {
+                assert!(other_pixels == *other.get(5).unwrap().image_data);
+            }
+        }
+    }
+
+    #[test]
+    pub fn sprite_sheets() {
+        // 61 frames of 120x120
+        let gif = read_gif_file(test_input("1bpp.gif")).unwrap();
+        let sheet = gif.sprite_sheet(None, "sheet \"1\".png").unwrap();
+        assert_eq!((sheet.columns, sheet.rows), (8, 8));
+
+        let (width, height, _, pixels) = read_png(&sheet.png);
+        assert_eq!((width, height), (960, 960));
+
+        let atlas: serde_json::Value = serde_json::from_str(&sheet.atlas).unwrap();
+        assert_eq!(atlas["meta"]["image"], "sheet \"1\".png");
+        assert_eq!(atlas["meta"]["size"]["w"], 960);
+        let frames = atlas["frames"].as_array().unwrap();
+        assert_eq!(frames.len(), 61);
+
+        for (i, entry) in frames.iter().enumerate() {
+            let frame = gif.get(i).unwrap();
+            assert_eq!(entry["duration"], u64::from(frame.delay) * 10);
+
+            let rect = &entry["frame"];
+            let x = rect["x"].as_u64().unwrap() as usize;
+            let y = rect["y"].as_u64().unwrap() as usize;
+            assert_eq!((x, y), (i % 8 * 120, i / 8 * 120));
+            for row in 0..120 {
+                let start = ((y + row) * width + x) * 4;
+                assert_eq!(
+                    pixels[start..start + 120 * 4],
+                    frame.image_data[row * 120 * 4..(row + 1) * 120 * 4]
+                );
+            }
+        }
+
+        let sheet = gif.sprite_sheet(Some(61), "strip.png").unwrap();
+        assert_eq!((sheet.columns, sheet.rows), (61, 1));
+    }
+
+    #[test]
+    pub fn sprite_sheet_too_large() {
+        // Two frames on an 8192x8192 canvas, which is as big as the default limits allow
+        let mut data = tiny_gif(&[], false);
+        data[6..10].copy_from_slice(&[0, 0x20, 0, 0x20]);
+        let frame = data[19..].to_vec();
+        data.extend(frame);
+        data.push(0x3b);
+
+        let gif = decode_lazy(data.into()).unwrap();
+        assert_eq!(gif.num_frames, 2);
+        assert!(matches!(
+            gif.sprite_sheet(None, "sheet.png"),
+            Err(EncodeError::TooLarge {
+                width: 16384,
+                height: 8192
+            })
+        ));
+
+        // Held to the limits the GIF was decoded with, rather than the defaults
+        let mut data = tiny_gif(&[], false);
+        data[6..10].copy_from_slice(&[100, 0, 100, 0]);
+        let frame = data[19..].to_vec();
+        data.extend(frame);
+        data.push(0x3b);
+        let decode_with_max = |max| {
+            let options = DecodeOptions {
+                limits: DecodeLimits {
+                    max_canvas_pixels: Some(max),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            decode_with_options(data.clone().into(), &options).unwrap()
+        };
+        assert!(matches!(
+            decode_with_max(100 * 100).sprite_sheet(None, "sheet.png"),
+            Err(EncodeError::TooLarge { .. })
+        ));
+        let sheet = decode_with_max(2 * 100 * 100)
+            .sprite_sheet(None, "sheet.png")
+            .unwrap();
+        assert_eq!((sheet.columns, sheet.rows), (2, 1));
+    }
+
+    #[test]
+    pub fn empty_canvas() {
+        // A 0x0 canvas, with a 1x1 frame hanging off the edge of it
+        let mut data = tiny_gif(&[], true);
+        data[6..10].copy_from_slice(&[0; 4]);
+        let gif = decode(data.into()).unwrap();
+        assert_eq!(gif.num_frames, 1);
+
+        assert!(matches!(gif.frame_png(0), Err(EncodeError::EmptyImage)));
+        assert!(matches!(
+            gif.frame_pngs().next(),
+            Some(Err(EncodeError::EmptyImage))
+        ));
+        assert!(matches!(
+            gif.sprite_sheet(None, "sheet.png"),
+            Err(EncodeError::EmptyImage)
+        ));
+        assert!(matches!(gif.to_apng(), Err(EncodeError::EmptyImage)));
+    }
+}
+
+mod cli {
+    use std::path::PathBuf;
+    use std::process::{Command, Output};
+
+    use gif_controls_decoder::decode;
+
+    use crate::util::*;
+
+    fn gif_controls(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_gif-controls"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    fn out_dir(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+    }
+
+    #[test]
+    pub fn expected_output() {
+        let dir = out_dir("cli-expected");
+        for name in ["1bpp", "interlaced"] {
+            let input = test_input(format!("{name}.gif"));
+            let args = ["expected", "--out", dir.to_str().unwrap()];
+            let output = gif_controls(&[&args[..], &[input.to_str().unwrap()]].concat());
+            assert!(output.status.success(), "{output:?}");
+
+            let decoded = read_gif_file(&input).unwrap();
+            compare_frames(&decoded, &read_bin_file(dir.join(format!("{name}.bin"))));
+            compare_meta(&decoded, dir.join(format!("{name}.json")));
+
+            let read_json = |path: PathBuf| -> serde_json::Value {
+                serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+            };
+            assert_eq!(
+                read_json(dir.join(format!("{name}.json"))),
+                read_json(test_output(format!("{name}.json")))
+            );
+        }
+    }
+
+    #[test]
+    pub fn inspect_json() {
+        let input = test_input("local-color-table.gif");
+        let output = gif_controls(&["inspect", "--json", input.to_str().unwrap()]);
+        assert!(output.status.success(), "{output:?}");
+        let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+        let gif = read_gif_file(&input).unwrap();
+        assert_eq!(info["width"], gif.canvas_width);
+        assert_eq!(info["loops"], 0);
+        assert_eq!(info["globalPalette"].as_array().unwrap().len(), 256);
+
+        let frames = info["frames"].as_array().unwrap();
+        assert_eq!(frames.len(), gif.num_frames);
+        for (entry, frame) in frames.iter().zip(gif.frames().map(Result::unwrap)) {
+            assert_eq!(entry["left"], frame.left);
+            assert_eq!(entry["delay"], frame.delay);
+            assert_eq!(
+                entry["transparent"].as_u64(),
+                frame.transparency_index.map(u64::from)
+            );
+            assert_eq!(
+                entry["localPalette"].as_array().map(Vec::len),
+                frame.local_palette.map(|palette| palette.len() / 3)
+            );
+        }
+    }
+
+    #[test]
+    pub fn convert_range() {
+        let input = test_input("1bpp.gif");
+        let out = out_dir("cli-convert.gif");
+        let output = gif_controls(&[
+            "convert",
+            "--start",
+            "2",
+            "--end",
+            "6",
+            "--out",
+            out.to_str().unwrap(),
+            input.to_str().unwrap(),
+        ]);
+        assert!(output.status.success(), "{output:?}");
+
+        let original = read_gif_file(&input).unwrap();
+        let converted = decode(std::fs::read(&out).unwrap().into()).unwrap();
+        assert_eq!(converted.num_frames, 4);
+        assert_eq!(converted.max_loops, original.max_loops);
+        for i in 0..4 {
+            let (a, b) = (original.get(i + 2).unwrap(), converted.get(i).unwrap());
+            assert!(a.image_data == b.image_data, "frame {i}");
+        }
+    }
+
+    #[test]
+    pub fn blocks_json() {
+        let input = test_input("dispose1.gif");
+        let output = gif_controls(&["blocks", "--json", input.to_str().unwrap()]);
+        assert!(output.status.success(), "{output:?}");
+        let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+        let blocks = info["blocks"].as_array().unwrap();
+        let images = blocks.iter().filter(|b| b["type"] == "image").count();
+        assert_eq!(images, read_gif_file(&input).unwrap().num_frames);
+        assert_eq!(blocks.last().unwrap()["type"], "trailer");
+        assert!(info["error"].is_null());
+        // One frame hangs off the edge of the canvas
+        assert_eq!(info["warnings"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    pub fn bad_arguments() {
+        let input = test_input("1bpp.gif");
+        for args in [
+            vec![],
+            vec!["unpack", input.to_str().unwrap()],
+            vec!["inspect", "--verbose", input.to_str().unwrap()],
+            vec!["convert", input.to_str().unwrap()],
+            vec!["extract", "--format", "jpeg", input.to_str().unwrap()],
+            vec!["inspect", "missing.gif"],
+        ] {
+            let output = gif_controls(&args);
+            assert!(!output.status.success(), "{args:?}");
+            assert!(output.stderr.starts_with(b"gif-controls: "), "{args:?}");
+        }
+    }
+}
+
+mod structure {
+    use std::fs;
+
+    use gif_controls_decoder::{
+        decode_lazy, read_structure, BlockKind, DecodeError, DisposalMethod, GifBlock, WarningKind,
+    };
+
+    use crate::util::*;
+
+    fn block(offset: u64, len: u64, kind: BlockKind) -> GifBlock {
+        GifBlock {
+            offset,
+            len,
+            kind,
+            sub_blocks: vec![],
+            children: vec![],
+        }
+    }
+
+    #[test]
+    pub fn tiny_gif_blocks() {
+        let comment = [0x21, 0xfe, 2, b'h', b'i', 1, b'!', 0];
+        let gfx = [0x21, 0xf9, 4, 0b1001, 7, 0, 1, 0];
+        let data = tiny_gif(&[&comment[..], &gfx].concat(), true);
+        let structure = read_structure(&data);
+        assert!(structure.error.is_none());
+        assert!(structure.warnings.is_empty());
+        assert_eq!(structure.end, data.len() as u64);
+
+        let mut screen = block(
+            6,
+            13,
+            BlockKind::LogicalScreenDescriptor {
+                width: 1,
+                height: 1,
+                background_index: 0,
+                aspect_ratio: 0,
+            },
+        );
+        screen.children = vec![block(13, 6, BlockKind::ColorTable { colors: 2 })];
+        let mut comment = block(19, 8, BlockKind::Comment);
+        comment.sub_blocks = vec![2, 1];
+        let mut gfx = block(
+            27,
+            8,
+            BlockKind::GraphicsControl {
+                disposal_method: DisposalMethod::RestoreBackground,
+                user_input: false,
+                delay: 7,
+                transparent: Some(1),
+            },
+        );
+        gfx.sub_blocks = vec![4];
+        let mut image_data = block(45, 5, BlockKind::ImageData { min_code_size: 2 });
+        image_data.sub_blocks = vec![2];
+        let mut image = block(
+            35,
+            15,
+            BlockKind::Image {
+                left: 0,
+                top: 0,
+                width: 1,
+                height: 1,
+                interlaced: false,
+            },
+        );
+        image.children = vec![image_data];
+
+        assert_eq!(
+            structure.blocks,
+            vec![
+                block(
+                    0,
+                    6,
+                    BlockKind::Header {
+                        version: "89a".into()
+                    }
+                ),
+                screen,
+                comment,
+                gfx,
+                image,
+                block(50, 1, BlockKind::Trailer),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn fixtures_cover_whole_file() {
+        for name in [
+            "1bpp.gif",
+            "dispose1.gif",
+            "earth.gif",
+            "interlaced.gif",
+            "local-color-table.gif",
+        ] {
+            let data = fs::read(test_input(name)).unwrap();
+            let structure = read_structure(&data);
+            assert!(structure.error.is_none(), "{name}");
+            assert_eq!(structure.end, data.len() as u64, "{name}");
+
+            // Each block starts where the one before it ended, and so does each child
+            let mut pos = 0;
+            let mut images = 0;
+            for block in &structure.blocks {
+                assert_eq!(block.offset, pos, "{name}");
+                pos += block.len;
+                if matches!(block.kind, BlockKind::Image { .. }) {
+                    images += 1;
+                }
+
+                let mut child_end = block.offset
+                    + match block.kind {
+                        BlockKind::Image { .. } => 10,
+                        _ => 7,
+                    };
+                for child in &block.children {
+                    assert_eq!(child.offset, child_end, "{name}");
+                    child_end += child.len;
+                }
+                if !block.children.is_empty() {
+                    assert_eq!(child_end, pos, "{name}");
+                }
+            }
+
+            let gif = decode_lazy(data.into()).unwrap();
+            assert_eq!(images, gif.num_frames, "{name}");
+            assert_eq!(structure.warnings, gif.warnings, "{name}");
+        }
+    }
+
+    #[test]
+    pub fn junk_and_truncation() {
+        let data = tiny_gif(&[0x00, 0x99, 0x98], true);
+        let structure = read_structure(&data);
+        assert!(structure.error.is_none());
+        assert_eq!(structure.blocks[2], block(19, 3, BlockKind::Unknown));
+        assert!(matches!(structure.blocks[3].kind, BlockKind::Image { .. }));
+        let kinds: Vec<_> = structure.warnings.iter().map(|w| &w.kind).collect();
+        assert_eq!(kinds, [&WarningKind::UnknownBlock(0)]);
+
+        // Cut off partway through the image data
+        let data = &tiny_gif(&[], true)[..32];
+        let structure = read_structure(data);
+        assert!(matches!(structure.error, Some(DecodeError::IO(_))));
+        assert_eq!(structure.blocks.len(), 2);
+        assert_eq!(structure.end, 19);
+
+        let structure = read_structure(b"GIF89");
+        assert!(structure.blocks.is_empty());
+        assert!(structure.error.is_some());
+    }
+}
+
+mod limits {
+    use std::fs;
+    use std::io;
+
+    use gif_controls_decoder::{
+        decode_indexed_with_options, decode_lazy_with_options, decode_with_options, encode,
+        DecodeError, DecodeLimits, DecodeOptions, EncodeFrame, EncodeOptions, FrameStream,
+        LimitKind, PixelFormat, PushDecoder,
+    };
+
+    use crate::util::*;
+
+    fn options(limits: DecodeLimits) -> DecodeOptions {
+        DecodeOptions {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    fn assert_limit<T>(result: Result<T, DecodeError>, expected: LimitKind) {
+        match result {
+            Err(DecodeError::LimitExceeded { kind, .. }) => assert_eq!(kind, expected),
+            Err(e) => panic!("expected {expected:?} limit, got {e}"),
+            Ok(_) => panic!("expected {expected:?} limit, but decoding succeeded"),
+        }
+    }
+
+    #[test]
+    pub fn huge_canvas() {
+        let mut data = tiny_gif(&[], true);
+        data[6..10].copy_from_slice(&[0xff; 4]);
+        let options = DecodeOptions::default();
+
+        assert_limit(
+            decode_with_options(data.clone().into(), &options),
+            LimitKind::CanvasPixels,
+        );
+        assert_limit(
+            decode_lazy_with_options(data.clone().into(), &options),
+            LimitKind::CanvasPixels,
+        );
+        assert_limit(
+            decode_indexed_with_options(data.clone().into(), &options),
+            LimitKind::CanvasPixels,
+        );
+        assert_limit(
+            FrameStream::with_options(io::Cursor::new(data.clone()), &options),
+            LimitKind::CanvasPixels,
+        );
+        assert_limit(
+            PushDecoder::with_options(&options).push(&data),
+            LimitKind::CanvasPixels,
+        );
+    }
+
+    #[test]
+    pub fn huge_frame() {
+        // A 1x1 canvas with a 65535x65535 frame on it, which would take 17 GB to decode
+        let mut data = tiny_gif(&[], true);
+        data[24..28].copy_from_slice(&[0xff; 4]);
+        let options = DecodeOptions {
+            recover: true,
+            ..Default::default()
+        };
+        assert_limit(
+            decode_with_options(data.clone().into(), &options),
+            LimitKind::CanvasPixels,
+        );
+        assert_limit(
+            decode_indexed_with_options(data.into(), &options),
+            LimitKind::CanvasPixels,
+        );
+    }
+
+    #[test]
+    pub fn frame_count() {
+        let data = fs::read(test_input("1bpp.gif")).unwrap().into_boxed_slice();
+        let limits = DecodeLimits {
+            max_frames: Some(60),
+            ..Default::default()
+        };
+        assert_limit(
+            decode_with_options(data.clone(), &options(limits)),
+            LimitKind::Frames,
+        );
+        assert_limit(
+            decode_lazy_with_options(data.clone(), &options(limits)),
+            LimitKind::Frames,
+        );
+
+        let limits = DecodeLimits {
+            max_frames: Some(61),
+            ..Default::default()
+        };
+        let gif = decode_with_options(data.clone(), &options(limits)).unwrap();
+        assert_eq!(gif.num_frames, 61);
+
+        // Fetching frames again doesn't count against the limit
+        let lazy = decode_lazy_with_options(data, &options(limits)).unwrap();
+        for i in [60, 0, 60, 30, 60] {
+            assert!(lazy.get(i).unwrap().image_data == gif.get(i).unwrap().image_data);
+        }
+    }
+
+    #[test]
+    pub fn output_bytes() {
+        // 61 frames of 120x120
+        let data = fs::read(test_input("1bpp.gif")).unwrap().into_boxed_slice();
+        let frame_bytes = 120 * 120 * 4;
+
+        // Not even one frame fits
+        let limits = DecodeLimits {
+            max_output_bytes: Some(frame_bytes - 1),
+            ..Default::default()
+        };
+        assert_limit(
+            decode_lazy_with_options(data.clone(), &options(limits)),
+            LimitKind::OutputBytes,
+        );
+        assert_limit(
+            FrameStream::with_options(io::Cursor::new(data.clone()), &options(limits)),
+            LimitKind::OutputBytes,
+        );
+
+        // Counted in the pixel format the frames are handed out in
+        let gray = DecodeOptions {
+            pixel_format: PixelFormat::Gray8,
+            ..options(limits)
+        };
+        assert_eq!(
+            decode_lazy_with_options(data.clone(), &gray)
+                .unwrap()
+                .num_frames,
+            61
+        );
+
+        // Streaming and lazy decoding only ever hold one frame, but decoding up front keeps
+        // all of them
+        let limits = DecodeLimits {
+            max_output_bytes: Some(frame_bytes),
+            ..Default::default()
+        };
+        let stream = FrameStream::with_options(io::Cursor::new(data.clone()), &options(limits));
+        assert_eq!(stream.unwrap().map(Result::unwrap).count(), 61);
+        let lazy = decode_lazy_with_options(data.clone(), &options(limits)).unwrap();
+        assert_eq!(lazy.frames().map(Result::unwrap).count(), 61);
+        assert_limit(
+            decode_with_options(data.clone(), &options(limits)),
+            LimitKind::OutputBytes,
+        );
+
+        // Each frame, plus a full canvas every 16 frames to composite from
+        let limits = DecodeLimits {
+            max_output_bytes: Some(65 * frame_bytes),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_with_options(data, &options(limits))
+                .unwrap()
+                .num_frames,
+            61
+        );
+    }
+
+    #[test]
+    pub fn long_animation() {
+        // 300 frames on a 1000x1000 canvas, which would be 1.2 GB as full canvases
+        let mut frames = vec![EncodeFrame::rgba(1, 1, vec![255; 4].into(), 1)];
+        for i in 1..300 {
+            let mut frame = EncodeFrame::rgba(1, 1, vec![i as u8, 0, 0, 255].into(), 1);
+            frame.left = i;
+            frame.top = i;
+            frames.push(frame);
+        }
+        let data: Box<[u8]> = encode(1000, 1000, &frames, &EncodeOptions::default())
+            .unwrap()
+            .into();
+        let options = DecodeOptions::default();
+
+        let gif = decode_with_options(data.clone(), &options).unwrap();
+        assert_eq!(gif.num_frames, 300);
+        let lazy = decode_lazy_with_options(data.clone(), &options).unwrap();
+        assert_eq!(lazy.num_frames, 300);
+        let stream = FrameStream::with_options(io::Cursor::new(data), &options).unwrap();
+        assert_eq!(stream.map(Result::unwrap).count(), 300);
+    }
+
+    #[test]
+    pub fn many_tiny_frames() {
+        // 1x1 frames on a 256x256 canvas, where decoding up front also keeps a copy of the
+        // whole canvas every 16 frames
+        let frames: Vec<_> = (0..40)
+            .map(|_| EncodeFrame::rgba(1, 1, vec![255; 4].into(), 1))
+            .collect();
+        let data: Box<[u8]> = encode(256, 256, &frames, &EncodeOptions::default())
+            .unwrap()
+            .into();
+        let (canvas_bytes, delta_bytes) = (256 * 256 * 4, 4);
+
+        // Enough for 32 frames, but not the keyframe the 33rd one needs
+        let limits = DecodeLimits {
+            max_output_bytes: Some(2 * canvas_bytes + 33 * delta_bytes),
+            ..Default::default()
+        };
+        assert_limit(
+            decode_with_options(data.clone(), &options(limits)),
+            LimitKind::OutputBytes,
+        );
+
+        let limits = DecodeLimits {
+            max_output_bytes: Some(3 * canvas_bytes + 40 * delta_bytes),
+            ..Default::default()
+        };
+        let gif = decode_with_options(data, &options(limits)).unwrap();
+        assert_eq!(gif.num_frames, 40);
+
+        assert!(DecodeLimits::default().max_output_bytes.is_some());
+    }
+
+    #[test]
+    pub fn lzw_output() {
+        // A 100x100 frame with its descriptor changed to say 1x1, so 9999 of its indices
+        // overflow it
+        let frame = EncodeFrame::rgba(100, 100, vec![255; 100 * 100 * 4].into(), 0);
+        let mut data = encode(100, 100, &[frame], &EncodeOptions::default()).unwrap();
+        let descriptor = data.iter().position(|&b| b == 0x2c).unwrap();
+        data[descriptor + 5..descriptor + 9].copy_from_slice(&[1, 0, 1, 0]);
+
+        // Even in recovery mode, which would otherwise ignore the overflow
+        let recover = DecodeOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let gif = decode_with_options(data.clone().into(), &recover).unwrap();
+        assert_eq!(gif.num_frames, 1);
+
+        let limits = DecodeLimits {
+            max_lzw_output: Some(1000),
+            ..Default::default()
+        };
+        let options = DecodeOptions { limits, ..recover };
+        assert_limit(
+            decode_with_options(data.clone().into(), &options),
+            LimitKind::LzwOutput,
+        );
+        assert_limit(
+            decode_indexed_with_options(data.into(), &options),
+            LimitKind::LzwOutput,
+        );
+    }
+}
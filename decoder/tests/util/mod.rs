@@ -8,9 +8,13 @@ use std::time::Instant;
 use byteorder::{ByteOrder, LE};
 use serde::{Deserialize, Serialize};
 
-use gif_controls_decoder::{decode, DecodeError, DecodedGif};
+use gif_controls_decoder::{
+    decode, decode_lazy, decode_with_options, DecodeError, DecodeOptions, DecodedGif,
+};
 use xz2::read::XzDecoder;
 
+pub mod png;
+
 pub fn resource_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-resources")
 }
@@ -23,6 +27,20 @@ pub fn test_output<P: Into<PathBuf>>(path: P) -> PathBuf {
     resource_dir().join("expected").join(path.into())
 }
 
+/// A 1x1 GIF with a two-color global palette, with `extensions` before its only frame
+pub fn tiny_gif(extensions: &[u8], trailer: bool) -> Vec<u8> {
+    let mut data = b"GIF89a".to_vec();
+    data.extend([1, 0, 1, 0, 0x80, 0, 0]);
+    data.extend([0, 0, 0, 255, 255, 255]);
+    data.extend(extensions);
+    data.extend([0x2c, 0, 0, 0, 0, 1, 0, 1, 0, 0]);
+    data.extend([2, 2, 0x44, 0x01, 0]);
+    if trailer {
+        data.push(0x3b);
+    }
+    data
+}
+
 pub struct Image {
     pub width: u16,
     pub height: u16,
@@ -30,10 +48,29 @@ pub struct Image {
 }
 
 pub fn read_gif_file<P: AsRef<Path> + Debug>(path: P) -> Result<DecodedGif, DecodeError> {
+    read_gif_file_with(path, decode)
+}
+
+pub fn read_gif_file_lazy<P: AsRef<Path> + Debug>(path: P) -> Result<DecodedGif, DecodeError> {
+    read_gif_file_with(path, decode_lazy)
+}
+
+pub fn read_gif_file_with_options<P: AsRef<Path> + Debug>(
+    path: P,
+    options: &DecodeOptions,
+) -> Result<DecodedGif, DecodeError> {
+    read_gif_file_with(path, |data| decode_with_options(data, options))
+}
+
+fn read_gif_file_with<P, F>(path: P, decode_fn: F) -> Result<DecodedGif, DecodeError>
+where
+    P: AsRef<Path> + Debug,
+    F: FnOnce(Box<[u8]>) -> Result<DecodedGif, DecodeError>,
+{
     let data = fs::read(&path).unwrap().into_boxed_slice();
 
     let start = Instant::now();
-    let result = decode(data);
+    let result = decode_fn(data);
     let elapsed = start.elapsed();
     println!(
         "> {} decoded in {:.2?}s",
@@ -70,11 +107,13 @@ pub fn read_bin_file<P: AsRef<Path>>(path: P) -> Vec<Image> {
 }
 
 pub fn compare_frames(gif: &DecodedGif, expected: &Vec<Image>) {
-    assert_eq!(gif.frames.len(), expected.len());
+    assert_eq!(gif.num_frames, expected.len());
     assert_eq!(gif.canvas_width, expected[0].width);
     assert_eq!(gif.canvas_height, expected[0].height);
 
-    for (frame_num, (actual, expected)) in zip(&gif.frames, expected).enumerate() {
+    for (frame_num, (actual, expected)) in
+        zip(gif.frames().map(Result::unwrap), expected).enumerate()
+    {
         let v1 = &actual.image_data.to_vec();
         let v2 = &expected.pixel_data;
 
@@ -126,9 +165,14 @@ pub fn compare_meta(gif: &DecodedGif, meta_path: impl AsRef<Path>) {
     assert_eq!(gif.canvas_width, meta.width);
     assert_eq!(gif.canvas_height, meta.height);
     assert_eq!(gif.max_loops, meta.loops);
-    assert_eq!(gif.frames.len(), meta.frames.len());
-
-    for (index, (gframe, mframe)) in gif.frames.iter().zip(meta.frames.iter()).enumerate() {
+    assert_eq!(gif.num_frames, meta.frames.len());
+
+    for (index, (gframe, mframe)) in gif
+        .frames()
+        .map(Result::unwrap)
+        .zip(meta.frames.iter())
+        .enumerate()
+    {
         assert_eq!(gframe.left, mframe.left, "frame {} left", index);
         assert_eq!(gframe.top, mframe.top, "frame {} top", index);
         assert_eq!(gframe.width, mframe.width, "frame {} width", index);
@@ -0,0 +1,231 @@
+//! Just enough of a PNG reader to check what the encoder writes: 8-bit channels, and deflate
+//! with stored or fixed Huffman blocks.
+
+pub struct Chunk {
+    pub kind: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// Splits a PNG into chunks, checking the signature and every checksum.
+pub fn read_chunks(png: &[u8]) -> Vec<Chunk> {
+    assert_eq!(png[..8], [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+
+    let mut chunks = vec![];
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let body = &rest[4..8 + len];
+        let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+        assert_eq!(crc32(body), crc);
+
+        chunks.push(Chunk {
+            kind: body[..4].try_into().unwrap(),
+            data: body[4..].to_vec(),
+        });
+        rest = &rest[12 + len..];
+    }
+
+    assert_eq!(&chunks.last().unwrap().kind, b"IEND");
+    chunks
+}
+
+pub fn be_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Decompresses and unfilters image data with `bpp` bytes per pixel.
+pub fn decode_image(width: usize, height: usize, bpp: usize, zlib: &[u8]) -> Vec<u8> {
+    let filtered = inflate(zlib);
+    let stride = width * bpp;
+    assert_eq!(filtered.len(), (stride + 1) * height);
+
+    let mut out: Vec<u8> = Vec::with_capacity(stride * height);
+    for (y, row) in filtered.chunks_exact(stride + 1).enumerate() {
+        let (filter, row) = (row[0], &row[1..]);
+        for (i, &byte) in row.iter().enumerate() {
+            let left = if i >= bpp {
+                out[y * stride + i - bpp]
+            } else {
+                0
+            };
+            let up = if y > 0 { out[(y - 1) * stride + i] } else { 0 };
+            let up_left = if y > 0 && i >= bpp {
+                out[(y - 1) * stride + i - bpp]
+            } else {
+                0
+            };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => {
+                    let p = i16::from(left) + i16::from(up) - i16::from(up_left);
+                    let (pa, pb, pc) = (
+                        (p - i16::from(left)).abs(),
+                        (p - i16::from(up)).abs(),
+                        (p - i16::from(up_left)).abs(),
+                    );
+                    if pa <= pb && pa <= pc {
+                        left
+                    } else if pb <= pc {
+                        up
+                    } else {
+                        up_left
+                    }
+                }
+                f => panic!("unknown filter {f}"),
+            };
+            out.push(byte.wrapping_add(predicted));
+        }
+    }
+
+    out
+}
+
+fn inflate(zlib: &[u8]) -> Vec<u8> {
+    assert_eq!(zlib[0] & 0x0f, 8, "not deflate");
+    let mut bits = Bits {
+        data: zlib,
+        pos: 16,
+    };
+    let mut out = vec![];
+
+    loop {
+        let last = bits.take(1) == 1;
+        match bits.take(2) {
+            0 => {
+                bits.pos = bits.pos.div_ceil(8) * 8;
+                let len = bits.take(16);
+                assert_eq!(bits.take(16), !len & 0xffff);
+                for _ in 0..len {
+                    out.push(bits.take(8) as u8);
+                }
+            }
+            1 => inflate_fixed(&mut bits, &mut out),
+            t => panic!("unsupported block type {t}"),
+        }
+        if last {
+            break;
+        }
+    }
+
+    let end = bits.pos.div_ceil(8);
+    let adler = u32::from_be_bytes(zlib[end..end + 4].try_into().unwrap());
+    assert_eq!(adler, adler32(&out));
+    out
+}
+
+fn inflate_fixed(bits: &mut Bits, out: &mut Vec<u8>) {
+    const LENGTH_BASES: [usize; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const DISTANCE_BASES: [usize; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+
+    loop {
+        let mut code = bits.take_huffman(7);
+        let symbol = if code <= 0x17 {
+            256 + code
+        } else {
+            code = code << 1 | bits.take(1);
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => 280 + code - 0xc0,
+                _ => 144 + (code << 1 | bits.take(1)) - 0x190,
+            }
+        };
+
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return,
+            _ => {
+                let i = symbol - 257;
+                let extra = if (8..28).contains(&i) { i / 4 - 1 } else { 0 };
+                let len = LENGTH_BASES[i] + bits.take(extra);
+
+                let d = bits.take_huffman(5);
+                let extra = if d >= 4 { d / 2 - 1 } else { 0 };
+                let dist = DISTANCE_BASES[d] + bits.take(extra);
+
+                for _ in 0..len {
+                    out.push(out[out.len() - dist]);
+                }
+            }
+        }
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: usize) -> usize {
+        let mut value = 0;
+        for i in 0..n {
+            let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+            value |= usize::from(bit) << i;
+            self.pos += 1;
+        }
+        value
+    }
+
+    /// Huffman codes start from their most significant bit
+    fn take_huffman(&mut self, n: usize) -> usize {
+        (0..n).fold(0, |code, _| code << 1 | self.take(1))
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |mut crc, &byte| {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+        crc
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + u32::from(byte)) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+/// Reads a single image PNG into its color type and pixels.
+pub fn read_png(png: &[u8]) -> (usize, usize, u8, Vec<u8>) {
+    let chunks = read_chunks(png);
+    let ihdr = &chunks[0].data;
+    let (width, height) = (be_u32(ihdr, 0) as usize, be_u32(ihdr, 4) as usize);
+    let color_type = ihdr[9];
+    let bpp = match color_type {
+        0 => 1,
+        2 => 3,
+        6 => 4,
+        t => panic!("unexpected color type {t}"),
+    };
+
+    let data: Vec<u8> = chunks
+        .iter()
+        .filter(|chunk| &chunk.kind == b"IDAT")
+        .flat_map(|chunk| chunk.data.iter().copied())
+        .collect();
+    (
+        width,
+        height,
+        color_type,
+        decode_image(width, height, bpp, &data),
+    )
+}